@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use google_cloud_googleapis::spanner::admin::database::v1::{
+    backup, Backup, Database, DatabaseDialect, ListBackupsRequest, ListDatabasesRequest,
+};
+use google_cloud_googleapis::spanner::admin::instance::v1::ListInstancesRequest;
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use log::{info, warn};
+use time::OffsetDateTime;
+
+use crate::retention::{format_retention_period, parse_retention_period, MIN_RETENTION_PERIOD};
+use crate::ToOffsetDateTime;
+
+/// The recoverability of one database: how far back it can be read, whether it can be
+/// dropped and its latest backup.
+#[derive(Debug, Clone)]
+pub struct DatabaseAudit {
+    pub name: String,
+    pub dialect: DatabaseDialect,
+    pub retention_period: time::Duration,
+    /// Earliest time the database can be read at, which is unknown while it is being created
+    pub earliest_version_time: Option<OffsetDateTime>,
+    pub drop_protection: bool,
+    /// Name and version time of the latest ready backup
+    pub latest_backup: Option<(String, OffsetDateTime)>,
+}
+
+impl DatabaseAudit {
+    /// Return whether the database keeps versions for the default period of one hour only.
+    pub fn default_retention(&self) -> bool {
+        self.retention_period <= MIN_RETENTION_PERIOD
+    }
+
+    /// Render the audit as a line listing the database and its recovery settings.
+    pub fn summary(&self) -> Result<String> {
+        let rfc3339 = &time::format_description::well_known::Rfc3339;
+        Ok(format!(
+            "{}  {}  retention={}  earliest={}  drop-protection={}  latest-backup={}{}",
+            self.name,
+            self.dialect.as_str_name(),
+            format_retention_period(self.retention_period),
+            match &self.earliest_version_time {
+                Some(earliest_version_time) => earliest_version_time.format(rfc3339)?,
+                None => "unknown".to_string(),
+            },
+            if self.drop_protection { "on" } else { "off" },
+            match &self.latest_backup {
+                Some((name, version_time)) =>
+                    format!("{} ({})", name, version_time.format(rfc3339)?),
+                None => "none".to_string(),
+            },
+            if self.default_retention() {
+                "  DEFAULT-RETENTION"
+            } else {
+                ""
+            }
+        ))
+    }
+}
+
+/// Return the latest ready backup of each database in a list of backups, keyed by database.
+fn latest_backups(backups: Vec<Backup>) -> HashMap<String, (String, OffsetDateTime)> {
+    let mut latest: HashMap<String, (String, OffsetDateTime)> = HashMap::new();
+    for backup in backups
+        .into_iter()
+        .filter(|backup| backup.state == backup::State::Ready as i32)
+    {
+        let Some(version_time) = backup.version_time.map(|ts| ts.to_offset_date_time()) else {
+            continue;
+        };
+        match latest.get(&backup.database) {
+            Some((_, latest_time)) if *latest_time >= version_time => {}
+            _ => {
+                latest.insert(backup.database, (backup.name, version_time));
+            }
+        }
+    }
+    latest
+}
+
+/// Logic to audit the recoverability of every database in an instance, or in every instance
+/// of a project.
+pub struct Auditor<'a> {
+    pub admin_client: &'a AdminClient,
+    pub project: String,
+    pub instance: Option<String>,
+}
+
+impl<'a> Auditor<'a> {
+    /// Return the instances to audit.
+    async fn instances(&self) -> Result<Vec<String>> {
+        if let Some(instance) = &self.instance {
+            return Ok(vec![instance.clone()]);
+        }
+        Ok(self
+            .admin_client
+            .instance()
+            .list_instances(
+                ListInstancesRequest {
+                    parent: format!("projects/{}", self.project),
+                    ..Default::default()
+                },
+                None,
+            )
+            .await?
+            .into_iter()
+            .map(|instance| instance.name)
+            .collect())
+    }
+
+    /// Audit every database in an instance.
+    async fn audit_instance(&self, instance: &str) -> Result<Vec<DatabaseAudit>> {
+        let databases: Vec<Database> = self
+            .admin_client
+            .database()
+            .list_databases(
+                ListDatabasesRequest {
+                    parent: instance.to_string(),
+                    page_size: 0,
+                    page_token: "".to_string(),
+                },
+                None,
+            )
+            .await?;
+        let mut latest_backups = latest_backups(
+            self.admin_client
+                .database()
+                .list_backups(
+                    ListBackupsRequest {
+                        parent: instance.to_string(),
+                        filter: "".to_string(),
+                        page_size: 0,
+                        page_token: "".to_string(),
+                    },
+                    None,
+                )
+                .await?,
+        );
+
+        databases
+            .iter()
+            .map(|database| {
+                Ok(DatabaseAudit {
+                    name: database.name.clone(),
+                    dialect: DatabaseDialect::from_i32(database.database_dialect)
+                        .unwrap_or(DatabaseDialect::GoogleStandardSql),
+                    retention_period: parse_retention_period(&database.version_retention_period)?,
+                    earliest_version_time: database
+                        .earliest_version_time
+                        .as_ref()
+                        .map(|ts| ts.to_offset_date_time()),
+                    drop_protection: database.enable_drop_protection,
+                    latest_backup: latest_backups.remove(&database.name),
+                })
+            })
+            .collect()
+    }
+
+    /// Execute the audit, returning the audit of each database.
+    pub async fn run(&self) -> Result<Vec<DatabaseAudit>> {
+        let mut audits = vec![];
+        for instance in self.instances().await? {
+            info!("❔ Auditing databases in {}...", instance);
+            audits.extend(self.audit_instance(&instance).await?);
+        }
+
+        let flagged = audits
+            .iter()
+            .filter(|audit| audit.default_retention())
+            .collect::<Vec<_>>();
+        for audit in &flagged {
+            warn!(
+                "{} keeps versions for the default one hour only.",
+                audit.name
+            );
+        }
+        for audit in audits
+            .iter()
+            .filter(|audit| audit.earliest_version_time.is_none())
+        {
+            warn!("{} has no earliest version time yet.", audit.name);
+        }
+        info!(
+            "ℹ️ {} databases audited, {} with the default retention period",
+            audits.len(),
+            flagged.len()
+        );
+        Ok(audits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_cloud_googleapis::spanner::admin::database::v1::DatabaseDialect;
+    use time::{ext::NumericalDuration, macros::datetime};
+
+    use super::DatabaseAudit;
+
+    /// Test that a database is summarised with its settings, flagging the default retention.
+    #[test]
+    fn test_summary() {
+        let mut audit = DatabaseAudit {
+            name: "projects/p/instances/i/databases/d".to_string(),
+            dialect: DatabaseDialect::Postgresql,
+            retention_period: 1.hours(),
+            earliest_version_time: Some(datetime!(2023-03-01 23:00 UTC)),
+            drop_protection: false,
+            latest_backup: None,
+        };
+        assert_eq!(
+            audit.summary().unwrap(),
+            "projects/p/instances/i/databases/d  POSTGRESQL  retention=1h  \
+             earliest=2023-03-01T23:00:00Z  drop-protection=off  latest-backup=none  \
+             DEFAULT-RETENTION"
+        );
+
+        audit.retention_period = 7.days();
+        audit.drop_protection = true;
+        audit.latest_backup = Some(("b".to_string(), datetime!(2023-03-01 22:00 UTC)));
+        assert_eq!(
+            audit.summary().unwrap(),
+            "projects/p/instances/i/databases/d  POSTGRESQL  retention=7d  \
+             earliest=2023-03-01T23:00:00Z  drop-protection=on  \
+             latest-backup=b (2023-03-01T22:00:00Z)"
+        );
+
+        audit.earliest_version_time = None;
+        assert!(audit.summary().unwrap().contains("  earliest=unknown  "));
+    }
+}
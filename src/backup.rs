@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Result};
+use clap::{arg, Args};
+use google_cloud_googleapis::spanner::admin::database::v1::{
+    Backup, CreateBackupMetadata, CreateBackupRequest,
+};
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use indicatif::ProgressBar;
+use log::{debug, info};
+use time::{ext::NumericalDuration, OffsetDateTime};
+
+use crate::operations::backup_operation;
+use crate::{parse_timestamp, ToOffsetDateTime, ToTimestamp};
+
+/// Interval between polls of a long-running backup operation.
+pub const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Command line options controlling how point-in-time backups are created.
+#[derive(Args, Debug, Clone)]
+pub struct BackupOptions {
+    /// Backup identifier (optional, generated if not specified)
+    #[arg(long)]
+    pub backup_id: Option<String>,
+    /// Number of hours to retain the backup for
+    #[arg(long, default_value_t = 168, conflicts_with = "expire_at")]
+    pub retain_hours: i64,
+    /// Absolute expiry time of the backup (optional)
+    #[arg(long, value_parser=parse_timestamp)]
+    pub expire_at: Option<OffsetDateTime>,
+}
+
+impl BackupOptions {
+    /// Return the requested backup identifier, or generate a unique one.
+    pub fn backup_id(&self) -> String {
+        self.backup_id
+            .clone()
+            .unwrap_or_else(|| format!("backup-{}", uuid::Uuid::new_v4().simple()))
+    }
+
+    /// Return the expiration policy described by the options.
+    pub fn expiration(&self) -> ExpirationPolicy {
+        match self.expire_at {
+            Some(ts) => ExpirationPolicy::ExpireAt(ts),
+            None => ExpirationPolicy::RetainFor(self.retain_hours.hours()),
+        }
+    }
+}
+
+/// Determines when a backup is deleted by Spanner.
+#[derive(Debug, Clone, Copy)]
+pub enum ExpirationPolicy {
+    /// Retain the backup for a period after its creation.
+    RetainFor(time::Duration),
+    /// Expire the backup at a fixed time.
+    ExpireAt(OffsetDateTime),
+}
+
+impl ExpirationPolicy {
+    /// Spanner requires backups to be retained for at least six hours.
+    const MINIMUM_RETENTION: time::Duration = time::Duration::hours(6);
+    /// Spanner allows backups to be retained for at most 366 days.
+    const MAXIMUM_RETENTION: time::Duration = time::Duration::days(366);
+
+    /// Return the equivalent option of `gcloud spanner backups create`.
+    pub fn gcloud_option(&self) -> Result<String> {
+        Ok(match self {
+            ExpirationPolicy::RetainFor(duration) => {
+                format!("--retention-period={}h", duration.whole_hours())
+            }
+            ExpirationPolicy::ExpireAt(ts) => format!(
+                "--expiration-date={}",
+                ts.format(&time::format_description::well_known::Rfc3339)?
+            ),
+        })
+    }
+
+    /// Calculate the expiry time for a backup created at `now`.
+    pub fn expire_time(&self, now: OffsetDateTime) -> Result<OffsetDateTime> {
+        let expire_time = match self {
+            ExpirationPolicy::RetainFor(duration) => now + *duration,
+            ExpirationPolicy::ExpireAt(ts) => *ts,
+        };
+
+        if expire_time - now < Self::MINIMUM_RETENTION {
+            return Err(anyhow!(
+                "Backup expiry time {} must be at least 6 hours in the future.",
+                expire_time
+            ));
+        }
+
+        if expire_time - now > Self::MAXIMUM_RETENTION {
+            return Err(anyhow!(
+                "Backup expiry time {} must be at most 366 days in the future.",
+                expire_time
+            ));
+        }
+
+        Ok(expire_time)
+    }
+}
+
+/// Logic to create a backup of a database at a specific version time.
+pub struct BackupCreator<'a> {
+    pub admin_client: &'a AdminClient,
+    pub instance: String,
+    pub database: String,
+    pub backup_id: String,
+    pub version_time: OffsetDateTime,
+    pub expiration: ExpirationPolicy,
+}
+
+impl<'a> BackupCreator<'a> {
+    /// Return the full backup path.
+    fn backup_path(&self) -> String {
+        format!("{}/backups/{}", self.instance, self.backup_id)
+    }
+
+    /// Return the progress of the create backup operation, if it is available.
+    async fn progress(&self) -> Result<Option<i32>> {
+        let operation = backup_operation::<CreateBackupMetadata>(
+            self.admin_client,
+            &self.instance,
+            "CreateBackupMetadata",
+            &self.backup_path(),
+        )
+        .await?;
+
+        Ok(operation
+            .and_then(|(_, metadata)| metadata.progress)
+            .map(|progress| progress.progress_percent))
+    }
+
+    /// Execute the backup creation, polling the operation until it completes.
+    pub async fn run(&self) -> Result<Backup> {
+        let expire_time = self.expiration.expire_time(OffsetDateTime::now_utc())?;
+
+        info!(
+            "💾 Creating backup {} at version time {} (expires {})...",
+            self.backup_path(),
+            self.version_time,
+            expire_time
+        );
+
+        let mut operation = self
+            .admin_client
+            .database()
+            .create_backup(
+                CreateBackupRequest {
+                    parent: self.instance.clone(),
+                    backup_id: self.backup_id.clone(),
+                    backup: Some(Backup {
+                        database: self.database.clone(),
+                        version_time: Some(self.version_time.to_timestamp()),
+                        expire_time: Some(expire_time.to_timestamp()),
+                        ..Default::default()
+                    }),
+                    encryption_config: None,
+                },
+                None,
+            )
+            .await?;
+
+        let bar = ProgressBar::new(100);
+        let backup = loop {
+            if let Some(backup) = operation.poll().await? {
+                break backup;
+            }
+
+            match self.progress().await {
+                Ok(Some(percent)) => bar.set_position(percent as u64),
+                Ok(None) => {}
+                Err(e) => debug!("Unable to read backup progress: {}", e),
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+        bar.finish();
+
+        info!(
+            "✅ Created backup {} at version time {}",
+            backup.name,
+            backup
+                .version_time
+                .as_ref()
+                .map(|ts| ts.to_offset_date_time())
+                .unwrap_or(self.version_time)
+        );
+
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::{ext::NumericalDuration, macros::datetime, OffsetDateTime};
+
+    use super::ExpirationPolicy;
+
+    /// Test that expiry times are validated against the Spanner retention limits.
+    #[test]
+    fn test_expiration_policy() {
+        let now = OffsetDateTime::now_utc();
+
+        assert_eq!(
+            ExpirationPolicy::RetainFor(7.days())
+                .expire_time(now)
+                .unwrap(),
+            now + 7.days()
+        );
+        assert!(ExpirationPolicy::RetainFor(1.hours())
+            .expire_time(now)
+            .is_err());
+        assert!(ExpirationPolicy::ExpireAt(now + 400.days())
+            .expire_time(now)
+            .is_err());
+    }
+
+    /// Test that the equivalent gcloud option follows the kind of policy.
+    #[test]
+    fn test_gcloud_option() {
+        assert_eq!(
+            ExpirationPolicy::RetainFor(168.hours())
+                .gcloud_option()
+                .unwrap(),
+            "--retention-period=168h"
+        );
+        assert_eq!(
+            ExpirationPolicy::ExpireAt(datetime!(2023-03-08 00:00 UTC))
+                .gcloud_option()
+                .unwrap(),
+            "--expiration-date=2023-03-08T00:00:00Z"
+        );
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use google_cloud_spanner::client::Client;
+use google_cloud_spanner::reader::AsyncIterator;
+use google_cloud_spanner::statement::Statement;
+use log::debug;
+use prost_types::value::Kind;
+use time::OffsetDateTime;
+
+use crate::value::SpannerValue;
+
+/// A column of a table modified in a data change record.
+#[derive(Debug, Clone)]
+pub struct ColumnType {
+    pub name: String,
+    /// Spanner type of the column, as a JSON object such as `{"code": "INT64"}`
+    pub r#type: serde_json::Value,
+    pub is_primary_key: bool,
+}
+
+/// A modification to one row recorded in a change stream. Keys and values are JSON objects
+/// keyed by column name.
+#[derive(Debug, Clone)]
+pub struct Mod {
+    pub keys: serde_json::Value,
+    pub old_values: serde_json::Value,
+}
+
+/// A data change record read from a change stream: the changes made by one transaction to
+/// one table within one partition.
+#[derive(Debug, Clone)]
+pub struct DataChangeRecord {
+    pub commit_timestamp: OffsetDateTime,
+    /// Order of the record within its transaction, as a zero-padded number
+    pub record_sequence: String,
+    pub server_transaction_id: String,
+    pub transaction_tag: String,
+    pub is_system_transaction: bool,
+    pub table_name: String,
+    pub column_types: Vec<ColumnType>,
+    pub mod_type: String,
+    pub value_capture_type: String,
+    pub mods: Vec<Mod>,
+}
+
+impl DataChangeRecord {
+    /// Render the record as a line listing its transaction, tag, change and row keys.
+    pub fn summary(&self) -> Result<String> {
+        Ok(format!(
+            "{}  {}  {}  {} {}  {}",
+            self.commit_timestamp
+                .format(&time::format_description::well_known::Rfc3339)?,
+            self.server_transaction_id,
+            if self.is_system_transaction {
+                "(system)"
+            } else if self.transaction_tag.is_empty() {
+                "(untagged)"
+            } else {
+                &self.transaction_tag
+            },
+            self.mod_type,
+            self.table_name,
+            self.mods
+                .iter()
+                .map(|m| m.keys.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))
+    }
+}
+
+/// Return the named field of a struct value.
+fn field(value: &SpannerValue, name: &str) -> Result<SpannerValue> {
+    let fields = value
+        .r#type
+        .struct_type
+        .as_ref()
+        .map(|struct_type| struct_type.fields.as_slice())
+        .unwrap_or_default();
+    let index = fields
+        .iter()
+        .position(|f| f.name == name)
+        .ok_or_else(|| anyhow!("Change record has no field {}.", name))?;
+    match &value.kind {
+        Kind::ListValue(list) => Ok(SpannerValue {
+            kind: list.values[index]
+                .kind
+                .clone()
+                .unwrap_or(Kind::NullValue(0)),
+            r#type: fields[index].r#type.clone().unwrap_or_default(),
+        }),
+        _ => Err(anyhow!("Change record field {} is not a struct.", name)),
+    }
+}
+
+/// Return the elements of an array value, which are none if it is `NULL`.
+fn elements(value: &SpannerValue) -> Result<Vec<SpannerValue>> {
+    let element_type = value
+        .r#type
+        .array_element_type
+        .as_deref()
+        .cloned()
+        .unwrap_or_default();
+    match &value.kind {
+        Kind::ListValue(list) => Ok(list
+            .values
+            .iter()
+            .map(|element| SpannerValue {
+                kind: element.kind.clone().unwrap_or(Kind::NullValue(0)),
+                r#type: element_type.clone(),
+            })
+            .collect()),
+        Kind::NullValue(_) => Ok(vec![]),
+        _ => Err(anyhow!("Change record value is not an array.")),
+    }
+}
+
+/// Return the value of a string field, which is empty if it is `NULL`.
+fn string_field(value: &SpannerValue, name: &str) -> Result<String> {
+    match field(value, name)?.kind {
+        Kind::StringValue(s) => Ok(s),
+        Kind::NullValue(_) => Ok("".to_string()),
+        _ => Err(anyhow!("Change record field {} is not a string.", name)),
+    }
+}
+
+/// Return the value of a boolean field, which is `false` if it is `NULL`.
+fn bool_field(value: &SpannerValue, name: &str) -> Result<bool> {
+    match field(value, name)?.kind {
+        Kind::BoolValue(b) => Ok(b),
+        Kind::NullValue(_) => Ok(false),
+        _ => Err(anyhow!("Change record field {} is not a boolean.", name)),
+    }
+}
+
+/// Return the value of a JSON field, which is `null` if it is `NULL`.
+fn json_field(value: &SpannerValue, name: &str) -> Result<serde_json::Value> {
+    match field(value, name)?.kind {
+        Kind::StringValue(s) => Ok(serde_json::from_str(&s)?),
+        Kind::NullValue(_) => Ok(serde_json::Value::Null),
+        _ => Err(anyhow!("Change record field {} is not JSON.", name)),
+    }
+}
+
+/// Return the value of a timestamp field.
+fn timestamp_field(value: &SpannerValue, name: &str) -> Result<OffsetDateTime> {
+    Ok(OffsetDateTime::parse(
+        &string_field(value, name)?,
+        &time::format_description::well_known::Rfc3339,
+    )?)
+}
+
+/// Return whether a change stream name can be used unquoted in the name of its read
+/// function, which cannot be quoted.
+fn valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Logic to read the data change records of a change stream committed within a period,
+/// following every partition of the stream.
+pub struct ChangeStreamReader<'a> {
+    pub client: &'a Client,
+    pub change_stream: String,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+}
+
+impl<'a> ChangeStreamReader<'a> {
+    /// Query one partition of the change stream from the given timestamp, adding its data
+    /// change records and returning its child partitions with their start timestamps.
+    async fn read_partition(
+        &self,
+        token: Option<&str>,
+        start: OffsetDateTime,
+        records: &mut Vec<DataChangeRecord>,
+    ) -> Result<Vec<(String, OffsetDateTime)>> {
+        let mut stmt = Statement::new(format!(
+            "SELECT ChangeRecord FROM READ_{}(\
+             start_timestamp => @start, end_timestamp => @end, \
+             partition_token => {}, heartbeat_milliseconds => 10000)",
+            self.change_stream,
+            if token.is_some() { "@token" } else { "NULL" }
+        ));
+        stmt.add_param("start", &start);
+        stmt.add_param("end", &self.end);
+        if let Some(token) = token {
+            stmt.add_param("token", &token.to_string());
+        }
+
+        // Change stream queries must use a strong single-use read-only transaction.
+        let mut tx = self.client.single().await?;
+        let mut rows = tx.query(stmt).await?;
+        let mut children = vec![];
+        while let Some(row) = rows.next().await? {
+            for change_record in elements(&row.column::<SpannerValue>(0)?)? {
+                for record in elements(&field(&change_record, "data_change_record")?)? {
+                    records.push(DataChangeRecord {
+                        commit_timestamp: timestamp_field(&record, "commit_timestamp")?,
+                        record_sequence: string_field(&record, "record_sequence")?,
+                        server_transaction_id: string_field(&record, "server_transaction_id")?,
+                        transaction_tag: string_field(&record, "transaction_tag")?,
+                        is_system_transaction: bool_field(&record, "is_system_transaction")?,
+                        table_name: string_field(&record, "table_name")?,
+                        column_types: elements(&field(&record, "column_types")?)?
+                            .iter()
+                            .map(|c| {
+                                Ok(ColumnType {
+                                    name: string_field(c, "name")?,
+                                    r#type: json_field(c, "type")?,
+                                    is_primary_key: bool_field(c, "is_primary_key")?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                        mod_type: string_field(&record, "mod_type")?,
+                        value_capture_type: string_field(&record, "value_capture_type")?,
+                        mods: elements(&field(&record, "mods")?)?
+                            .iter()
+                            .map(|m| {
+                                Ok(Mod {
+                                    keys: json_field(m, "keys")?,
+                                    old_values: json_field(m, "old_values")?,
+                                })
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                    });
+                }
+                for record in elements(&field(&change_record, "child_partitions_record")?)? {
+                    let start = timestamp_field(&record, "start_timestamp")?;
+                    for partition in elements(&field(&record, "child_partitions")?)? {
+                        children.push((string_field(&partition, "token")?, start));
+                    }
+                }
+            }
+        }
+        Ok(children)
+    }
+
+    /// Read every data change record in the period, ordered by commit timestamp and then
+    /// by their order within each transaction.
+    pub async fn run(&self) -> Result<Vec<DataChangeRecord>> {
+        if !valid_name(&self.change_stream) {
+            return Err(anyhow!(
+                "Change stream name {} is not valid. Names must start with a letter and contain \
+                 only letters, digits and underscores.",
+                self.change_stream
+            ));
+        }
+        let mut records = vec![];
+        let mut queue = self.read_partition(None, self.start, &mut records).await?;
+        let mut seen = HashSet::new();
+
+        // Partitions which merge have several parents, but are only read once.
+        while let Some((token, start)) = queue.pop() {
+            if !seen.insert(token.clone()) {
+                continue;
+            }
+            debug!("Reading change stream partition from {}...", start);
+            let children = self
+                .read_partition(Some(&token), start.max(self.start), &mut records)
+                .await?;
+            queue.extend(children);
+        }
+
+        records.sort_by(|a, b| {
+            (a.commit_timestamp, &a.record_sequence).cmp(&(b.commit_timestamp, &b.record_sequence))
+        });
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use time::macros::datetime;
+
+    use super::{valid_name, DataChangeRecord, Mod};
+
+    /// Test that only plain identifiers are accepted as change stream names.
+    #[test]
+    fn test_valid_name() {
+        assert!(valid_name("SingersStream"));
+        assert!(valid_name("Singers_Stream_2"));
+        assert!(!valid_name(""));
+        assert!(!valid_name("_Stream"));
+        assert!(!valid_name("2Stream"));
+        assert!(!valid_name("Stream(); DROP TABLE Singers"));
+        assert!(!valid_name("Stream-1"));
+    }
+
+    /// Test that a record is summarised with its transaction, tag, change and keys.
+    #[test]
+    fn test_summary() {
+        let mut record = DataChangeRecord {
+            commit_timestamp: datetime!(2023-03-01 23:34:43.023443 UTC),
+            record_sequence: "00000000".to_string(),
+            server_transaction_id: "txn".to_string(),
+            transaction_tag: "app=billing".to_string(),
+            is_system_transaction: false,
+            table_name: "Singers".to_string(),
+            column_types: vec![],
+            mod_type: "DELETE".to_string(),
+            value_capture_type: "OLD_AND_NEW_VALUES".to_string(),
+            mods: vec![
+                Mod {
+                    keys: json!({"SingerId": "1"}),
+                    old_values: json!({}),
+                },
+                Mod {
+                    keys: json!({"SingerId": "2"}),
+                    old_values: json!({}),
+                },
+            ],
+        };
+        assert_eq!(
+            record.summary().unwrap(),
+            "2023-03-01T23:34:43.023443Z  txn  app=billing  DELETE Singers  \
+             {\"SingerId\":\"1\"}, {\"SingerId\":\"2\"}"
+        );
+
+        record.is_system_transaction = true;
+        assert!(record.summary().unwrap().contains("  (system)  "));
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use google_cloud_spanner::client::Client;
+use google_cloud_spanner::reader::AsyncIterator;
+use google_cloud_spanner::statement::Statement;
+use log::debug;
+use time::OffsetDateTime;
+
+use crate::changestream::ChangeStreamReader;
+use crate::read_timestamp;
+use crate::schema::quote_identifier;
+
+/// Logic to find the timestamps of the transactions committed within a period, from the
+/// commit timestamp columns of tables, or from a change stream.
+pub struct CommitFinder<'a> {
+    pub client: &'a Client,
+    pub tables: Vec<String>,
+    pub change_stream: Option<String>,
+}
+
+impl<'a> CommitFinder<'a> {
+    /// Return the columns of the tables which allow commit timestamps.
+    async fn commit_timestamp_columns(&self, at: &OffsetDateTime) -> Result<Vec<(String, String)>> {
+        let mut tx = self
+            .client
+            .single_with_timestamp_bound(read_timestamp(at))
+            .await?;
+        let mut rows = tx
+            .query(Statement::new(
+                "SELECT TABLE_NAME, COLUMN_NAME FROM INFORMATION_SCHEMA.COLUMN_OPTIONS \
+                 WHERE TABLE_SCHEMA = '' AND OPTION_NAME = 'allow_commit_timestamp' \
+                 AND OPTION_VALUE = 'TRUE'",
+            ))
+            .await?;
+
+        let mut columns = vec![];
+        while let Some(row) = rows.next().await? {
+            let table = row.column::<String>(0)?;
+            if self.tables.contains(&table) {
+                columns.push((table, row.column::<String>(1)?));
+            }
+        }
+        Ok(columns)
+    }
+
+    /// Return the commit timestamps in a table column which fall after the start of the
+    /// period, read at its end. Rows deleted within the period are not found.
+    async fn column_commits(
+        &self,
+        table: &str,
+        column: &str,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+    ) -> Result<Vec<OffsetDateTime>> {
+        let column = quote_identifier(column);
+        let mut stmt = Statement::new(format!(
+            "SELECT DISTINCT {} FROM {} WHERE {} > @start AND {} <= @end",
+            column,
+            quote_identifier(table),
+            column,
+            column
+        ));
+        stmt.add_param("start", start);
+        stmt.add_param("end", end);
+
+        let mut tx = self
+            .client
+            .single_with_timestamp_bound(read_timestamp(end))
+            .await?;
+        let mut rows = tx.query(stmt).await?;
+        let mut commits = vec![];
+        while let Some(row) = rows.next().await? {
+            commits.push(row.column::<OffsetDateTime>(0)?);
+        }
+        Ok(commits)
+    }
+
+    /// Return the distinct commit timestamps after `start` and up to `end`, in order.
+    pub async fn run(
+        &self,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+    ) -> Result<Vec<OffsetDateTime>> {
+        let mut commits = vec![];
+
+        for (table, column) in self.commit_timestamp_columns(end).await? {
+            let found = self.column_commits(&table, &column, start, end).await?;
+            debug!("{} commits found in {}.{}", found.len(), table, column);
+            commits.extend(found);
+        }
+
+        if let Some(change_stream) = &self.change_stream {
+            let records = ChangeStreamReader {
+                client: self.client,
+                change_stream: change_stream.clone(),
+                start: *start,
+                end: *end,
+            }
+            .run()
+            .await?;
+            for record in records.iter().filter(|r| r.commit_timestamp > *start) {
+                debug!(
+                    "Commit at {} by transaction {} to {}",
+                    record.commit_timestamp, record.server_transaction_id, record.table_name
+                );
+                commits.push(record.commit_timestamp);
+            }
+        }
+
+        commits.sort();
+        commits.dedup();
+        Ok(commits)
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::Result;
+use google_cloud_googleapis::spanner::admin::database::v1::UpdateDatabaseDdlMetadata;
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use time::OffsetDateTime;
+
+use crate::operations::list_database_operations;
+use crate::ToOffsetDateTime;
+
+/// A schema statement applied to a database, with the time it was committed.
+#[derive(Debug, Clone)]
+pub struct DdlStatement {
+    pub statement: String,
+    pub commit_timestamp: Option<OffsetDateTime>,
+    pub operation: String,
+}
+
+impl DdlStatement {
+    /// Return whether the statement drops the named table.
+    pub fn drops_table(&self, table: &str) -> bool {
+        let tokens = self
+            .statement
+            .split_whitespace()
+            .map(|token| token.to_uppercase())
+            .collect::<Vec<_>>();
+        let name = match tokens.as_slice() {
+            [drop, kw, if_, exists, name]
+                if drop == "DROP" && kw == "TABLE" && if_ == "IF" && exists == "EXISTS" =>
+            {
+                name
+            }
+            [drop, kw, name] if drop == "DROP" && kw == "TABLE" => name,
+            _ => return false,
+        };
+        name.trim_matches('`').eq_ignore_ascii_case(table)
+    }
+}
+
+/// Return the schema statements applied to a database, oldest first. Statements which have
+/// not yet been committed have no commit timestamp. Spanner retains operations for 7 days.
+pub async fn ddl_history(
+    admin_client: &AdminClient,
+    instance: &str,
+    database: &str,
+) -> Result<Vec<DdlStatement>> {
+    let operations = list_database_operations::<UpdateDatabaseDdlMetadata>(
+        admin_client,
+        instance,
+        "UpdateDatabaseDdlMetadata",
+        &format!("metadata.database={}", database),
+    )
+    .await?;
+
+    let mut statements = operations
+        .into_iter()
+        .flat_map(|(operation, _, metadata)| {
+            let commit_timestamps = metadata.commit_timestamps;
+            metadata
+                .statements
+                .into_iter()
+                .enumerate()
+                .map(move |(i, statement)| DdlStatement {
+                    statement,
+                    commit_timestamp: commit_timestamps.get(i).map(|ts| ts.to_offset_date_time()),
+                    operation: operation.clone(),
+                })
+        })
+        .collect::<Vec<_>>();
+
+    statements.sort_by_key(|s| (s.commit_timestamp.is_none(), s.commit_timestamp));
+    Ok(statements)
+}
+
+/// Return the latest timestamp before the named table was dropped, from the commit time of
+/// the most recent `DROP TABLE` statement.
+pub async fn pre_drop_timestamp(
+    admin_client: &AdminClient,
+    instance: &str,
+    database: &str,
+    table: &str,
+) -> Result<Option<OffsetDateTime>> {
+    Ok(ddl_history(admin_client, instance, database)
+        .await?
+        .into_iter()
+        .filter(|s| s.drops_table(table))
+        .filter_map(|s| s.commit_timestamp)
+        .max()
+        .map(|ts| ts - time::Duration::nanoseconds(1)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DdlStatement;
+
+    fn statement(sql: &str) -> DdlStatement {
+        DdlStatement {
+            statement: sql.to_string(),
+            commit_timestamp: None,
+            operation: "".to_string(),
+        }
+    }
+
+    /// Test that `DROP TABLE` statements are matched regardless of case and quoting.
+    #[test]
+    fn test_drops_table() {
+        assert!(statement("DROP TABLE Singers").drops_table("Singers"));
+        assert!(statement("drop table `singers`").drops_table("Singers"));
+        assert!(statement("DROP TABLE IF EXISTS Singers").drops_table("Singers"));
+        assert!(!statement("DROP TABLE Albums").drops_table("Singers"));
+        assert!(!statement("DROP INDEX Singers").drops_table("Singers"));
+    }
+}
@@ -0,0 +1,439 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use google_cloud_spanner::client::Client;
+use google_cloud_spanner::value::TimestampBound;
+use log::info;
+use serde_json::json;
+use time::OffsetDateTime;
+
+use crate::read_timestamp;
+use crate::schema::{quote_identifier, read_named_rows, TableSchema};
+use crate::value::{format_row, SpannerValue};
+
+/// Output format of a diff.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DiffFormat {
+    /// Human-readable table
+    Table,
+    /// Newline-delimited JSON objects, one per changed row
+    Json,
+}
+
+/// The kind of change made to a row between two timestamps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeType {
+    Inserted,
+    Deleted,
+    Modified,
+}
+
+impl ChangeType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ChangeType::Inserted => "inserted",
+            ChangeType::Deleted => "deleted",
+            ChangeType::Modified => "modified",
+        }
+    }
+}
+
+/// A row which differs between two timestamps. A row that did not exist at one of the
+/// timestamps has no values for it.
+#[derive(Debug, Clone)]
+pub struct RowDiff {
+    pub key: Vec<SpannerValue>,
+    pub before: Option<Vec<SpannerValue>>,
+    pub after: Option<Vec<SpannerValue>>,
+}
+
+impl RowDiff {
+    /// Return the kind of change made to the row.
+    pub fn change_type(&self) -> ChangeType {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeType::Inserted,
+            (_, None) => ChangeType::Deleted,
+            _ => ChangeType::Modified,
+        }
+    }
+
+    /// Return the indexes of the columns whose values changed.
+    pub fn changed_columns(&self) -> Vec<usize> {
+        match (&self.before, &self.after) {
+            (Some(before), Some(after)) => (0..before.len())
+                .filter(|&i| before[i] != after[i])
+                .collect(),
+            (Some(row), None) | (None, Some(row)) => (0..row.len()).collect(),
+            (None, None) => vec![],
+        }
+    }
+}
+
+/// The differences between the rows of a table or query at two timestamps, joined on key.
+#[derive(Debug, Clone)]
+pub struct TableDiff {
+    pub columns: Vec<String>,
+    pub key_columns: Vec<usize>,
+    pub rows: Vec<RowDiff>,
+}
+
+impl TableDiff {
+    /// Compare two sets of rows with the same columns, ordering the changes by key. Fails if
+    /// a key identifies more than one row on either side.
+    pub fn compute(
+        columns: Vec<String>,
+        key_columns: Vec<usize>,
+        before: Vec<Vec<SpannerValue>>,
+        after: Vec<Vec<SpannerValue>>,
+    ) -> Result<TableDiff> {
+        let duplicate = |key: &str| {
+            anyhow!(
+                "Key {} identifies more than one row, so the rows cannot be compared.",
+                key
+            )
+        };
+        let key = |row: &[SpannerValue]| -> Vec<SpannerValue> {
+            key_columns.iter().map(|&i| row[i].clone()).collect()
+        };
+
+        let mut rows: BTreeMap<String, RowDiff> = BTreeMap::new();
+        for row in before {
+            let key = key(&row);
+            let formatted = format_row(&key);
+            if rows.contains_key(&formatted) {
+                return Err(duplicate(&formatted));
+            }
+            rows.insert(
+                formatted,
+                RowDiff {
+                    key,
+                    before: Some(row),
+                    after: None,
+                },
+            );
+        }
+        for row in after {
+            let key = key(&row);
+            let formatted = format_row(&key);
+            let diff = rows.entry(formatted.clone()).or_insert(RowDiff {
+                key,
+                before: None,
+                after: None,
+            });
+            if diff.after.is_some() {
+                return Err(duplicate(&formatted));
+            }
+            diff.after = Some(row);
+        }
+
+        Ok(TableDiff {
+            columns,
+            key_columns,
+            rows: rows
+                .into_values()
+                .filter(|row| row.before != row.after)
+                .collect(),
+        })
+    }
+
+    /// Return the number of rows with each kind of change.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let count = |change_type| {
+            self.rows
+                .iter()
+                .filter(|row| row.change_type() == change_type)
+                .count()
+        };
+        (
+            count(ChangeType::Inserted),
+            count(ChangeType::Deleted),
+            count(ChangeType::Modified),
+        )
+    }
+
+    /// Render the diff as a human-readable table with a line per changed column.
+    pub fn to_table(&self) -> String {
+        let mut lines = vec![[
+            "change".to_string(),
+            "key".to_string(),
+            "column".to_string(),
+            "before".to_string(),
+            "after".to_string(),
+        ]];
+        for row in &self.rows {
+            let value = |values: &Option<Vec<SpannerValue>>, i: usize| {
+                values
+                    .as_ref()
+                    .map(|values| values[i].to_string())
+                    .unwrap_or_default()
+            };
+            for i in row.changed_columns() {
+                lines.push([
+                    row.change_type().as_str().to_string(),
+                    format_row(&row.key),
+                    self.columns[i].clone(),
+                    value(&row.before, i),
+                    value(&row.after, i),
+                ]);
+            }
+        }
+
+        let widths = (0..5)
+            .map(|i| {
+                lines
+                    .iter()
+                    .map(|line| line[i].chars().count())
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+        lines
+            .iter()
+            .map(|line| {
+                line.iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                    .collect::<Vec<_>>()
+                    .join(" | ")
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Render the diff as newline-delimited JSON objects, one per changed row.
+    pub fn to_json_lines(&self) -> String {
+        let object = |values: &[SpannerValue], columns: &[usize]| {
+            columns
+                .iter()
+                .map(|&i| (self.columns[i].clone(), values[i].to_json()))
+                .collect::<serde_json::Map<_, _>>()
+        };
+
+        self.rows
+            .iter()
+            .map(|row| {
+                let key = self
+                    .key_columns
+                    .iter()
+                    .zip(&row.key)
+                    .map(|(&i, value)| (self.columns[i].clone(), value.to_json()))
+                    .collect::<serde_json::Map<_, _>>();
+                let changed = row.changed_columns();
+                json!({
+                    "change": row.change_type().as_str(),
+                    "key": key,
+                    "before": row.before.as_ref().map(|values| object(values, &changed)),
+                    "after": row.after.as_ref().map(|values| object(values, &changed)),
+                })
+                .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Check that the columns read at both timestamps are the same and in the same order, as
+/// rows are compared column by column.
+fn check_same_columns(before: &[String], after: &[String]) -> Result<()> {
+    if before != after {
+        return Err(anyhow!(
+            "Columns changed between the timestamps, from ({}) to ({}).",
+            before.join(", "),
+            after.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Logic to compare the rows of a table or query at two timestamps.
+pub struct Differ<'a> {
+    pub client: &'a Client,
+    pub table: Option<String>,
+    pub query: Option<String>,
+    pub key: Vec<String>,
+    pub filter: String,
+    pub from: OffsetDateTime,
+    pub to: Option<OffsetDateTime>,
+}
+
+impl<'a> Differ<'a> {
+    /// Return the bound reading at the end timestamp, or the current time if there is none.
+    fn to_bound(&self) -> TimestampBound {
+        self.to
+            .as_ref()
+            .map(read_timestamp)
+            .unwrap_or_else(TimestampBound::strong_read)
+    }
+
+    /// Return the SQL to read and the key columns to join on. Tables are read with the
+    /// columns they have at the start timestamp, which must be the same at the end.
+    async fn source(&self) -> Result<(String, Vec<String>)> {
+        match (&self.table, &self.query) {
+            (Some(table), _) => {
+                let schema =
+                    TableSchema::read(self.client, read_timestamp(&self.from), table).await?;
+                let later = TableSchema::read(self.client, self.to_bound(), table).await?;
+                let names = |schema: &TableSchema| {
+                    schema
+                        .columns
+                        .iter()
+                        .map(|column| column.name.clone())
+                        .collect::<Vec<_>>()
+                };
+                check_same_columns(&names(&schema), &names(&later))?;
+                Ok((
+                    format!(
+                        "SELECT {} FROM {} WHERE {}",
+                        names(&schema)
+                            .iter()
+                            .map(|name| quote_identifier(name))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        quote_identifier(table),
+                        self.filter
+                    ),
+                    schema.primary_key,
+                ))
+            }
+            (None, Some(query)) => Ok((query.clone(), self.key.clone())),
+            (None, None) => Err(anyhow!("Either a table or a query is required.")),
+        }
+    }
+
+    /// Execute the comparison.
+    pub async fn run(&self) -> Result<TableDiff> {
+        let (sql, key) = self.source().await?;
+
+        info!(
+            "❔ Comparing `{}` at {} and {}...",
+            sql,
+            self.from,
+            self.to
+                .map(|ts| ts.to_string())
+                .unwrap_or_else(|| "now".to_string())
+        );
+        let (columns, before) =
+            read_named_rows(self.client, read_timestamp(&self.from), &sql).await?;
+        let (after_columns, after) = read_named_rows(self.client, self.to_bound(), &sql).await?;
+
+        // An empty result has no column metadata, so use whichever side has rows.
+        let columns = if columns.is_empty() {
+            after_columns
+        } else {
+            if !after_columns.is_empty() {
+                check_same_columns(&columns, &after_columns)?;
+            }
+            columns
+        };
+        let key_columns = key
+            .iter()
+            .map(|k| {
+                columns
+                    .iter()
+                    .position(|c| c == k)
+                    .ok_or_else(|| anyhow!("Key column {} not found in the results.", k))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let diff = TableDiff::compute(columns, key_columns, before, after)?;
+        let (inserted, deleted, modified) = diff.counts();
+        info!(
+            "ℹ️ {} rows inserted, {} deleted, {} modified",
+            inserted, deleted, modified
+        );
+        Ok(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_cloud_googleapis::spanner::v1::{Type, TypeCode};
+    use prost_types::value::Kind;
+
+    use super::{check_same_columns, ChangeType, TableDiff};
+    use crate::value::SpannerValue;
+
+    fn row(id: &str, name: &str) -> Vec<SpannerValue> {
+        vec![
+            SpannerValue {
+                kind: Kind::StringValue(id.to_string()),
+                r#type: Type {
+                    code: TypeCode::Int64.into(),
+                    ..Default::default()
+                },
+            },
+            SpannerValue {
+                kind: Kind::StringValue(name.to_string()),
+                r#type: Type {
+                    code: TypeCode::String.into(),
+                    ..Default::default()
+                },
+            },
+        ]
+    }
+
+    /// Test that rows are joined on key and classified by the kind of change.
+    #[test]
+    fn test_compute() {
+        let diff = TableDiff::compute(
+            vec!["id".to_string(), "name".to_string()],
+            vec![0],
+            vec![row("1", "a"), row("2", "b"), row("3", "c")],
+            vec![row("1", "a"), row("3", "d"), row("4", "e")],
+        )
+        .unwrap();
+
+        let changes = diff
+            .rows
+            .iter()
+            .map(|row| {
+                (
+                    row.key[0].to_string(),
+                    row.change_type(),
+                    row.changed_columns(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            changes,
+            vec![
+                ("2".to_string(), ChangeType::Deleted, vec![0, 1]),
+                ("3".to_string(), ChangeType::Modified, vec![1]),
+                ("4".to_string(), ChangeType::Inserted, vec![0, 1]),
+            ]
+        );
+        assert_eq!(diff.counts(), (1, 1, 1));
+    }
+
+    /// Test that a key identifying more than one row on either side is rejected.
+    #[test]
+    fn test_compute_duplicate_key() {
+        let columns = vec!["id".to_string(), "name".to_string()];
+        assert!(TableDiff::compute(
+            columns.clone(),
+            vec![0],
+            vec![row("1", "a"), row("1", "b")],
+            vec![row("1", "a")],
+        )
+        .is_err());
+        assert!(TableDiff::compute(
+            columns,
+            vec![0],
+            vec![row("1", "a")],
+            vec![row("1", "a"), row("1", "b")],
+        )
+        .is_err());
+    }
+
+    /// Test that rows are only compared when their columns match.
+    #[test]
+    fn test_check_same_columns() {
+        let columns = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert!(check_same_columns(&columns(&["id", "name"]), &columns(&["id", "name"])).is_ok());
+        assert!(check_same_columns(&columns(&["id", "name"]), &columns(&["name", "id"])).is_err());
+        assert!(check_same_columns(&columns(&["id", "name"]), &columns(&["id"])).is_err());
+    }
+}
@@ -0,0 +1,453 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use apache_avro::types::Value as AvroValue;
+use apache_avro::Schema as AvroSchema;
+use base64::Engine;
+use clap::ValueEnum;
+use google_cloud_googleapis::spanner::v1::{struct_type::Field, Type, TypeCode};
+use google_cloud_spanner::client::{Client, ReadOnlyTransactionOption};
+use google_cloud_spanner::reader::AsyncIterator;
+use google_cloud_spanner::statement::Statement;
+use indicatif::ProgressBar;
+use log::{info, warn};
+use prost_types::value::Kind;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use time::OffsetDateTime;
+
+use crate::read_timestamp;
+use crate::schema::quote_identifier;
+use crate::value::SpannerValue;
+
+/// Format of exported data files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Comma-separated values, with a header row
+    Csv,
+    /// Newline-delimited JSON objects
+    Json,
+    /// Avro object container files
+    Avro,
+}
+
+impl ExportFormat {
+    /// Return the file extension for the format.
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "jsonl",
+            ExportFormat::Avro => "avro",
+        }
+    }
+}
+
+/// A table or query to export.
+#[derive(Debug, Clone)]
+pub struct ExportSource {
+    pub name: String,
+    pub sql: String,
+}
+
+impl ExportSource {
+    /// Export every column of a table.
+    pub fn table(table: &str) -> ExportSource {
+        ExportSource {
+            name: table.to_string(),
+            sql: format!("SELECT * FROM {}", quote_identifier(table)),
+        }
+    }
+
+    /// Export the results of an arbitrary query.
+    pub fn query(index: usize, sql: &str) -> ExportSource {
+        ExportSource {
+            name: format!("query_{}", index),
+            sql: sql.to_string(),
+        }
+    }
+}
+
+/// Return the name of a result column, naming anonymous columns by position.
+fn column_name(fields: &[Field], i: usize) -> String {
+    match fields[i].name.as_str() {
+        "" => format!("column_{}", i),
+        name => name.to_string(),
+    }
+}
+
+/// Return whether an error says a query cannot be split into partitions, such as one with an
+/// aggregate, `ORDER BY` or `LIMIT` at its root.
+fn not_partitionable(message: &str) -> bool {
+    message.to_lowercase().contains("not root partitionable")
+}
+
+/// Return the first name which appears more than once, if there is one.
+fn duplicate_name<'n>(names: impl IntoIterator<Item = &'n str>) -> Option<&'n str> {
+    let mut seen = std::collections::HashSet::new();
+    names.into_iter().find(|name| !seen.insert(*name))
+}
+
+/// Convert a value to text for a CSV field. `NULL` is written as an empty field, and arrays
+/// are written as JSON.
+fn to_csv_field(value: &SpannerValue) -> String {
+    match &value.kind {
+        Kind::NullValue(_) => "".to_string(),
+        Kind::StringValue(s) => s.clone(),
+        Kind::BoolValue(b) => b.to_string(),
+        Kind::NumberValue(n) => n.to_string(),
+        Kind::ListValue(_) | Kind::StructValue(_) => value.to_json().to_string(),
+    }
+}
+
+/// Build the Avro type of a Spanner type. Every value is nullable.
+fn avro_type(r#type: &Type) -> serde_json::Value {
+    let inner = match TypeCode::from_i32(r#type.code) {
+        Some(TypeCode::Bool) => json!("boolean"),
+        Some(TypeCode::Int64) => json!("long"),
+        Some(TypeCode::Float64) => json!("double"),
+        Some(TypeCode::Bytes) => json!("bytes"),
+        Some(TypeCode::Array) => json!({
+            "type": "array",
+            "items": avro_type(&r#type.array_element_type.as_deref().cloned().unwrap_or_default()),
+        }),
+        _ => json!("string"),
+    };
+    json!(["null", inner])
+}
+
+/// Build an Avro record schema for the result columns.
+fn avro_schema(name: &str, fields: &[Field]) -> Result<AvroSchema> {
+    let schema = json!({
+        "type": "record",
+        "name": name.replace(|c: char| !c.is_ascii_alphanumeric(), "_"),
+        "fields": fields.iter().enumerate().map(|(i, field)| json!({
+            "name": column_name(fields, i),
+            "type": avro_type(&field.r#type.clone().unwrap_or_default()),
+        })).collect::<Vec<_>>(),
+    });
+    Ok(AvroSchema::parse(&schema)?)
+}
+
+/// Convert a value of the given Spanner type to a nullable Avro value.
+fn to_avro(kind: &Kind, r#type: &Type) -> Result<AvroValue> {
+    let value = match (kind, TypeCode::from_i32(r#type.code)) {
+        (Kind::NullValue(_), _) => return Ok(AvroValue::Union(0, Box::new(AvroValue::Null))),
+        (Kind::BoolValue(b), _) => AvroValue::Boolean(*b),
+        (Kind::StringValue(s), Some(TypeCode::Int64)) => AvroValue::Long(s.parse()?),
+        (Kind::NumberValue(n), _) => AvroValue::Double(*n),
+        (Kind::StringValue(s), Some(TypeCode::Float64)) => AvroValue::Double(s.parse()?),
+        (Kind::StringValue(s), Some(TypeCode::Bytes)) => {
+            AvroValue::Bytes(base64::engine::general_purpose::STANDARD.decode(s)?)
+        }
+        (Kind::StringValue(s), _) => AvroValue::String(s.clone()),
+        (Kind::ListValue(list), _) => {
+            let element_type = r#type
+                .array_element_type
+                .as_deref()
+                .cloned()
+                .unwrap_or_default();
+            AvroValue::Array(
+                list.values
+                    .iter()
+                    .map(|v| {
+                        to_avro(
+                            v.kind.as_ref().unwrap_or(&Kind::NullValue(0)),
+                            &element_type,
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>()?,
+            )
+        }
+        (Kind::StructValue(_), _) => return Err(anyhow!("STRUCT values cannot be exported.")),
+    };
+    Ok(AvroValue::Union(1, Box::new(value)))
+}
+
+/// Writes rows to a data file in one of the supported formats.
+enum RowWriter<'a> {
+    Csv(csv::Writer<File>),
+    Json(BufWriter<File>),
+    Avro(apache_avro::Writer<'a, File>),
+}
+
+impl<'a> RowWriter<'a> {
+    /// Create a data file, writing a header if the format requires one.
+    fn create(
+        path: &Path,
+        format: ExportFormat,
+        fields: &[Field],
+        schema: Option<&'a AvroSchema>,
+    ) -> Result<RowWriter<'a>> {
+        let file = File::create(path)?;
+        Ok(match (format, schema) {
+            (ExportFormat::Csv, _) => {
+                let mut writer = csv::Writer::from_writer(file);
+                writer.write_record((0..fields.len()).map(|i| column_name(fields, i)))?;
+                RowWriter::Csv(writer)
+            }
+            (ExportFormat::Json, _) => RowWriter::Json(BufWriter::new(file)),
+            (ExportFormat::Avro, Some(schema)) => {
+                RowWriter::Avro(apache_avro::Writer::new(schema, file))
+            }
+            (ExportFormat::Avro, None) => return Err(anyhow!("Avro schema is required.")),
+        })
+    }
+
+    /// Write a single row.
+    fn write(&mut self, fields: &[Field], row: &[SpannerValue]) -> Result<()> {
+        match self {
+            RowWriter::Csv(writer) => writer.write_record(row.iter().map(to_csv_field))?,
+            RowWriter::Json(writer) => {
+                let object = row
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (column_name(fields, i), value.to_json()))
+                    .collect::<serde_json::Map<_, _>>();
+                serde_json::to_writer(&mut *writer, &object)?;
+                writer.write_all(b"\n")?;
+            }
+            RowWriter::Avro(writer) => {
+                let record = row
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| {
+                        Ok((column_name(fields, i), to_avro(&value.kind, &value.r#type)?))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                writer.append(AvroValue::Record(record))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Flush any buffered rows to the file.
+    fn finish(self) -> Result<()> {
+        match self {
+            RowWriter::Csv(mut writer) => writer.flush()?,
+            RowWriter::Json(mut writer) => writer.flush()?,
+            RowWriter::Avro(writer) => {
+                writer.into_inner()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the SHA-256 checksum of a file as a hexadecimal string.
+fn checksum(path: &Path) -> Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Write every row of a result to a data file, returning the number of rows written.
+async fn write_rows(
+    rows: &mut impl AsyncIterator,
+    fields: &[Field],
+    writer: &mut RowWriter<'_>,
+) -> Result<u64> {
+    let mut row_count = 0;
+    while let Some(row) = rows.next().await? {
+        let values = (0..fields.len())
+            .map(|i| row.column::<SpannerValue>(i))
+            .collect::<Result<Vec<_>, _>>()?;
+        writer.write(fields, &values)?;
+        row_count += 1;
+    }
+    Ok(row_count)
+}
+
+/// Logic to export a consistent snapshot of tables and queries at a single read timestamp.
+pub struct Exporter<'a> {
+    pub client: &'a Client,
+    pub database: String,
+    pub sources: Vec<ExportSource>,
+    pub read_timestamp: OffsetDateTime,
+    pub format: ExportFormat,
+    pub output: PathBuf,
+}
+
+impl<'a> Exporter<'a> {
+    /// Return the result columns of a query, without reading any rows.
+    async fn fields(&self, sql: &str) -> Result<Vec<Field>> {
+        let mut tx = self
+            .client
+            .single_with_timestamp_bound(read_timestamp(&self.read_timestamp))
+            .await?;
+        let mut rows = tx
+            .query(Statement::new(format!("SELECT * FROM ({}) LIMIT 0", sql)))
+            .await?;
+        while rows.next().await?.is_some() {}
+        Ok(rows.columns_metadata().to_vec())
+    }
+
+    /// Export a single source to a data file, returning its manifest entry.
+    async fn export_source(&self, source: &ExportSource) -> Result<serde_json::Value> {
+        let fields = self.fields(&source.sql).await?;
+        // Columns are keyed by name in JSON objects and Avro records.
+        let names = (0..fields.len())
+            .map(|i| column_name(&fields, i))
+            .collect::<Vec<_>>();
+        if let Some(name) = duplicate_name(names.iter().map(|name| name.as_str())) {
+            return Err(anyhow!(
+                "Column {} appears more than once in {}. Alias the columns to export them.",
+                name,
+                source.name
+            ));
+        }
+        let schema = match self.format {
+            ExportFormat::Avro => Some(avro_schema(&source.name, &fields)?),
+            _ => None,
+        };
+
+        let file_name = format!("{}.{}", source.name, self.format.extension());
+        let path = self.output.join(&file_name);
+        let mut writer = RowWriter::create(&path, self.format, &fields, schema.as_ref())?;
+
+        // Each source is read in its own batch transaction, but at the same exact read
+        // timestamp, so all sources share a single consistent snapshot of the database.
+        let mut tx = self
+            .client
+            .batch_read_only_transaction_with_option(ReadOnlyTransactionOption {
+                timestamp_bound: read_timestamp(&self.read_timestamp),
+                ..Default::default()
+            })
+            .await?;
+        let partitions = match tx.partition_query(Statement::new(&source.sql)).await {
+            Ok(partitions) => Some(partitions),
+            Err(e) if not_partitionable(&e.to_string()) => {
+                warn!(
+                    "{} is not root partitionable. Reading it with a single query instead.",
+                    source.name
+                );
+                None
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let bar = ProgressBar::new(partitions.as_ref().map_or(1, |p| p.len()) as u64);
+        bar.set_message(source.name.clone());
+        let mut row_count: u64 = 0;
+        match partitions {
+            Some(partitions) => {
+                for partition in partitions {
+                    let mut rows = tx.execute(partition, None).await?;
+                    row_count += write_rows(&mut rows, &fields, &mut writer).await?;
+                    bar.inc(1);
+                }
+            }
+            None => {
+                // The query still reads at the read timestamp of the batch transaction.
+                let mut rows = tx.query(Statement::new(&source.sql)).await?;
+                row_count += write_rows(&mut rows, &fields, &mut writer).await?;
+                bar.inc(1);
+            }
+        }
+        bar.finish();
+        writer.finish()?;
+
+        info!(
+            "  {}: {} rows written to {}",
+            source.name, row_count, file_name
+        );
+
+        Ok(json!({
+            "name": source.name,
+            "sql": source.sql,
+            "file": file_name,
+            "rows": row_count,
+            "sha256": checksum(&path)?,
+        }))
+    }
+
+    /// Execute the export, writing a data file per source and a manifest describing them.
+    pub async fn run(&self) -> Result<()> {
+        if let Some(name) = duplicate_name(self.sources.iter().map(|source| source.name.as_str())) {
+            return Err(anyhow!(
+                "More than one source would be exported to {}.{}.",
+                name,
+                self.format.extension()
+            ));
+        }
+        std::fs::create_dir_all(&self.output)?;
+        info!(
+            "💾 Exporting {} sources at {} to {}...",
+            self.sources.len(),
+            self.read_timestamp,
+            self.output.display()
+        );
+
+        let mut files = vec![];
+        for source in &self.sources {
+            files.push(self.export_source(source).await?);
+        }
+
+        let manifest = json!({
+            "database": self.database,
+            "read_timestamp": self.read_timestamp.format(&time::format_description::well_known::Rfc3339)?,
+            "format": self.format.extension(),
+            "files": files,
+        });
+        let manifest_path = self.output.join("manifest.json");
+        serde_json::to_writer_pretty(File::create(&manifest_path)?, &manifest)?;
+
+        info!("✅ Export manifest written to {}", manifest_path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_cloud_googleapis::spanner::v1::{Type, TypeCode};
+    use prost_types::value::Kind;
+
+    use super::{duplicate_name, not_partitionable, to_avro};
+    use apache_avro::types::Value as AvroValue;
+
+    /// Test that Spanner wire values are converted to nullable Avro values.
+    #[test]
+    fn test_to_avro() {
+        let int64 = Type {
+            code: TypeCode::Int64.into(),
+            ..Default::default()
+        };
+        let bytes = Type {
+            code: TypeCode::Bytes.into(),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            to_avro(&Kind::StringValue("42".to_string()), &int64).unwrap(),
+            AvroValue::Union(1, Box::new(AvroValue::Long(42)))
+        );
+        assert_eq!(
+            to_avro(&Kind::NullValue(0), &int64).unwrap(),
+            AvroValue::Union(0, Box::new(AvroValue::Null))
+        );
+        assert_eq!(
+            to_avro(&Kind::StringValue("aGk=".to_string()), &bytes).unwrap(),
+            AvroValue::Union(1, Box::new(AvroValue::Bytes(b"hi".to_vec())))
+        );
+    }
+
+    /// Test that names appearing more than once are found.
+    #[test]
+    fn test_duplicate_name() {
+        assert_eq!(duplicate_name(["Singers", "query_0"]), None);
+        assert_eq!(
+            duplicate_name(["query_0", "Singers", "query_0"]),
+            Some("query_0")
+        );
+    }
+
+    /// Test that the error for a query which cannot be partitioned is recognised.
+    #[test]
+    fn test_not_partitionable() {
+        assert!(not_partitionable(
+            "Query is not root partitionable since it does not have a DistributedUnion at the root."
+        ));
+        assert!(!not_partitionable("Table not found: Singers"));
+    }
+}
@@ -0,0 +1,563 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use google_cloud_googleapis::spanner::v1::{Mutation, Type, TypeCode};
+use google_cloud_spanner::client::Client;
+use google_cloud_spanner::key::{Key, KeySet};
+use google_cloud_spanner::mutation::{delete, insert, update};
+use google_cloud_spanner::statement::ToKind;
+use google_cloud_spanner::value::TimestampBound;
+use log::{info, warn};
+use prost_types::{value::Kind, ListValue};
+use time::OffsetDateTime;
+
+use crate::changestream::{ChangeStreamReader, DataChangeRecord};
+use crate::read_timestamp;
+use crate::revert::{apply_if_unchanged, ConflictPolicy, ExpectedRow};
+use crate::schema::{quote_identifier, read_keys};
+use crate::undelete::MAX_MUTATIONS_PER_COMMIT;
+use crate::value::{format_row, SpannerValue};
+
+/// Build a Spanner type from its JSON representation in a change stream.
+fn type_from_json(value: &serde_json::Value) -> Type {
+    Type {
+        code: value["code"]
+            .as_str()
+            .and_then(TypeCode::from_str_name)
+            .unwrap_or(TypeCode::Unspecified)
+            .into(),
+        array_element_type: value
+            .get("array_element_type")
+            .map(|element_type| Box::new(type_from_json(element_type))),
+        ..Default::default()
+    }
+}
+
+/// Convert a column value from its JSON representation in a change stream to the wire
+/// representation of the given type.
+fn kind_from_json(value: &serde_json::Value, r#type: &Type) -> Kind {
+    match value {
+        serde_json::Value::Null => Kind::NullValue(0),
+        serde_json::Value::Bool(b) => Kind::BoolValue(*b),
+        serde_json::Value::Number(n) if r#type.code == TypeCode::Int64 as i32 => {
+            Kind::StringValue(n.to_string())
+        }
+        serde_json::Value::Number(n) => Kind::NumberValue(n.as_f64().unwrap_or_default()),
+        serde_json::Value::String(s) => Kind::StringValue(s.clone()),
+        serde_json::Value::Array(values) => {
+            let element_type = r#type
+                .array_element_type
+                .as_deref()
+                .cloned()
+                .unwrap_or_default();
+            Kind::ListValue(ListValue {
+                values: values
+                    .iter()
+                    .map(|value| prost_types::Value {
+                        kind: Some(kind_from_json(value, &element_type)),
+                    })
+                    .collect(),
+            })
+        }
+        // Values of JSON columns are written as their serialised text.
+        serde_json::Value::Object(_) => Kind::StringValue(value.to_string()),
+    }
+}
+
+/// The kind of write which undoes a modification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InverseOp {
+    Delete,
+    Update,
+    Insert,
+}
+
+/// A write to one row which undoes a modification made by a transaction.
+#[derive(Debug, Clone)]
+pub struct InverseChange {
+    pub op: InverseOp,
+    pub table: String,
+    pub key: Vec<(String, SpannerValue)>,
+    pub values: Vec<(String, SpannerValue)>,
+    /// Non-key columns written by the transaction, compared to find later changes to the row
+    pub written: Vec<String>,
+}
+
+impl InverseChange {
+    /// Return the primary key values of the row.
+    fn key_values(&self) -> Vec<SpannerValue> {
+        self.key.iter().map(|(_, value)| value.clone()).collect()
+    }
+
+    /// Return the number of mutations the change counts towards the limit of one commit:
+    /// one for each column written, or one for a deleted row.
+    pub fn mutation_count(&self) -> usize {
+        match self.op {
+            InverseOp::Delete => 1,
+            InverseOp::Update | InverseOp::Insert => self.key.len() + self.values.len(),
+        }
+    }
+
+    /// Build the mutation making the change.
+    pub fn to_mutation(&self) -> Mutation {
+        let columns = self
+            .key
+            .iter()
+            .chain(&self.values)
+            .map(|(name, _)| name.as_str())
+            .collect::<Vec<_>>();
+        let values = self
+            .key
+            .iter()
+            .chain(&self.values)
+            .map(|(_, value)| value as &dyn ToKind)
+            .collect::<Vec<_>>();
+
+        match self.op {
+            InverseOp::Delete => {
+                let key_set: KeySet = vec![Key::composite(
+                    &self
+                        .key
+                        .iter()
+                        .map(|(_, value)| value as &dyn ToKind)
+                        .collect::<Vec<_>>(),
+                )]
+                .into();
+                delete(&self.table, key_set)
+            }
+            InverseOp::Update => update(&self.table, &columns, &values),
+            InverseOp::Insert => insert(&self.table, &columns, &values),
+        }
+    }
+
+    /// Render the change as a DML statement.
+    pub fn to_sql(&self) -> Result<String> {
+        let table = quote_identifier(&self.table);
+        let condition = self
+            .key
+            .iter()
+            .map(|(name, value)| {
+                Ok(format!(
+                    "{} = {}",
+                    quote_identifier(name),
+                    value.to_sql_literal()?
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(" AND ");
+
+        Ok(match self.op {
+            InverseOp::Delete => format!("DELETE FROM {} WHERE {};", table, condition),
+            InverseOp::Update => format!(
+                "UPDATE {} SET {} WHERE {};",
+                table,
+                self.values
+                    .iter()
+                    .map(|(name, value)| {
+                        Ok(format!(
+                            "{} = {}",
+                            quote_identifier(name),
+                            value.to_sql_literal()?
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", "),
+                condition
+            ),
+            InverseOp::Insert => {
+                let columns = self.key.iter().chain(&self.values).collect::<Vec<_>>();
+                format!(
+                    "INSERT INTO {} ({}) VALUES ({});",
+                    table,
+                    columns
+                        .iter()
+                        .map(|(name, _)| quote_identifier(name))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    columns
+                        .iter()
+                        .map(|(_, value)| value.to_sql_literal())
+                        .collect::<Result<Vec<_>>>()?
+                        .join(", ")
+                )
+            }
+        })
+    }
+}
+
+/// Read the key or non-key values of a JSON object of columns, typed using the columns of
+/// the record.
+fn typed_values(
+    record: &DataChangeRecord,
+    values: &serde_json::Value,
+    key: bool,
+) -> Result<Vec<(String, SpannerValue)>> {
+    let Some(values) = values.as_object() else {
+        return Ok(vec![]);
+    };
+    // Follow the column order of the record, rather than the order of the JSON object.
+    record
+        .column_types
+        .iter()
+        .filter(|column| column.is_primary_key == key && values.contains_key(&column.name))
+        .map(|column| {
+            let r#type = type_from_json(&column.r#type);
+            Ok((
+                column.name.clone(),
+                SpannerValue {
+                    kind: kind_from_json(&values[&column.name], &r#type),
+                    r#type,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Build the changes which undo the data change records of a transaction, in the reverse
+/// order to which they were made. Inserted rows are deleted, updated columns are restored to
+/// their old values and deleted rows are inserted again.
+pub fn invert(records: &[DataChangeRecord]) -> Result<Vec<InverseChange>> {
+    // Records are ordered within the transaction by their sequence, not as they were read.
+    let mut records = records.iter().collect::<Vec<_>>();
+    records.sort_by(|a, b| a.record_sequence.cmp(&b.record_sequence));
+
+    let mut changes = vec![];
+    for record in records.into_iter().rev() {
+        if record.value_capture_type != "OLD_AND_NEW_VALUES" {
+            return Err(anyhow!(
+                "Change stream records of {} capture {}, but OLD_AND_NEW_VALUES is required.",
+                record.table_name,
+                record.value_capture_type
+            ));
+        }
+
+        let op = match record.mod_type.as_str() {
+            "INSERT" => InverseOp::Delete,
+            "UPDATE" => InverseOp::Update,
+            "DELETE" => InverseOp::Insert,
+            other => return Err(anyhow!("Unknown modification type {}.", other)),
+        };
+        let written = record
+            .column_types
+            .iter()
+            .filter(|column| !column.is_primary_key)
+            .map(|column| column.name.clone())
+            .collect::<Vec<_>>();
+        for m in record.mods.iter().rev() {
+            changes.push(InverseChange {
+                op,
+                table: record.table_name.clone(),
+                key: typed_values(record, &m.keys, true)?,
+                values: match op {
+                    InverseOp::Delete => vec![],
+                    InverseOp::Update | InverseOp::Insert => {
+                        typed_values(record, &m.old_values, false)?
+                    }
+                },
+                written: written.clone(),
+            });
+        }
+    }
+    Ok(changes)
+}
+
+/// Logic to undo a single transaction from its change stream records, either applying the
+/// inverse changes in one transaction or rendering them as DML for review.
+pub struct TransactionInverter<'a> {
+    pub client: &'a Client,
+    pub change_stream: String,
+    pub transaction_id: Option<String>,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub conflict_policy: ConflictPolicy,
+    pub apply: bool,
+}
+
+impl<'a> TransactionInverter<'a> {
+    /// Read the data change records of the transaction.
+    async fn records(&self) -> Result<Vec<DataChangeRecord>> {
+        let records = ChangeStreamReader {
+            client: self.client,
+            change_stream: self.change_stream.clone(),
+            start: self.start,
+            end: self.end,
+        }
+        .run()
+        .await?;
+
+        let transaction_id = match &self.transaction_id {
+            Some(transaction_id) => transaction_id.clone(),
+            None => {
+                let mut transaction_ids = records
+                    .iter()
+                    .map(|record| record.server_transaction_id.clone())
+                    .collect::<Vec<_>>();
+                transaction_ids.sort();
+                transaction_ids.dedup();
+                match transaction_ids.as_slice() {
+                    [transaction_id] => transaction_id.clone(),
+                    [] => return Err(anyhow!("No transaction found in the change stream.")),
+                    _ => {
+                        return Err(anyhow!(
+                            "Several transactions found ({}), specify one with --transaction-id.",
+                            transaction_ids.join(", ")
+                        ))
+                    }
+                }
+            }
+        };
+
+        let records = records
+            .into_iter()
+            .filter(|record| record.server_transaction_id == transaction_id)
+            .collect::<Vec<_>>();
+        if records.is_empty() {
+            return Err(anyhow!(
+                "Transaction {} not found in the change stream.",
+                transaction_id
+            ));
+        }
+        info!(
+            "ℹ️ Transaction {} committed at {} with {} data change records",
+            transaction_id,
+            records[0].commit_timestamp,
+            records.len()
+        );
+        Ok(records)
+    }
+
+    /// Read the given columns of the given rows of a table, keyed by primary key.
+    async fn read_by_key(
+        &self,
+        bound: TimestampBound,
+        table: &str,
+        columns: &[String],
+        keys: &[&[SpannerValue]],
+        key_len: usize,
+    ) -> Result<HashMap<String, Vec<SpannerValue>>> {
+        Ok(read_keys(self.client, bound, table, columns, keys)
+            .await?
+            .into_iter()
+            .map(|row| (format_row(&row[..key_len]), row))
+            .collect())
+    }
+
+    /// Return whether each changed row has been changed again since the transaction, by
+    /// comparing the columns it wrote just after it committed with their current values,
+    /// together with the current values, which must be unchanged when the inverse is applied.
+    async fn changed_since(
+        &self,
+        changes: &[InverseChange],
+        commit_timestamp: &OffsetDateTime,
+    ) -> Result<Vec<(bool, ExpectedRow)>> {
+        // Rows are read together when they are in the same table and had the same columns
+        // written.
+        let mut groups: BTreeMap<(&str, Vec<String>), Vec<usize>> = BTreeMap::new();
+        for (i, change) in changes.iter().enumerate() {
+            let mut columns = change
+                .key
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>();
+            columns.extend(change.written.iter().cloned());
+            groups
+                .entry((change.table.as_str(), columns))
+                .or_default()
+                .push(i);
+        }
+
+        let mut changed = vec![None; changes.len()];
+        for ((table, columns), indices) in groups {
+            let key_len = changes[indices[0]].key.len();
+            let keys = indices
+                .iter()
+                .map(|i| changes[*i].key_values())
+                .collect::<Vec<_>>();
+            let keys = keys.iter().map(|key| key.as_slice()).collect::<Vec<_>>();
+            let after = self
+                .read_by_key(
+                    read_timestamp(commit_timestamp),
+                    table,
+                    &columns,
+                    &keys,
+                    key_len,
+                )
+                .await?;
+            let current = self
+                .read_by_key(
+                    TimestampBound::strong_read(),
+                    table,
+                    &columns,
+                    &keys,
+                    key_len,
+                )
+                .await?;
+
+            for (i, key) in indices.iter().zip(&keys) {
+                let formatted = format_row(key);
+                changed[*i] = Some((
+                    after.get(&formatted) != current.get(&formatted),
+                    ExpectedRow {
+                        table: table.to_string(),
+                        columns: columns.clone(),
+                        key: key.to_vec(),
+                        values: current.get(&formatted).cloned(),
+                    },
+                ));
+            }
+        }
+        // Every change is in exactly one group.
+        Ok(changed.into_iter().flatten().collect())
+    }
+
+    /// Execute the inversion, returning the inverse changes as DML statements.
+    pub async fn run(&self) -> Result<Vec<String>> {
+        let records = self.records().await?;
+        let commit_timestamp = records[0].commit_timestamp;
+        let changes = invert(&records)?;
+
+        // Rows changed again since the transaction hold good writes, which are kept unless
+        // they are to be overwritten.
+        let changed = self.changed_since(&changes, &commit_timestamp).await?;
+        let mut conflicting_rows = 0;
+        let mut kept = vec![];
+        let mut expected = vec![];
+        for (change, (changed, current)) in changes.into_iter().zip(changed) {
+            if changed {
+                conflicting_rows += 1;
+                warn!(
+                    "  {}: row {} changed since the transaction",
+                    change.table,
+                    format_row(&change.key_values())
+                );
+                if self.conflict_policy != ConflictPolicy::Overwrite {
+                    continue;
+                }
+            }
+            kept.push(change);
+            expected.push(current);
+        }
+        if self.conflict_policy == ConflictPolicy::Report && conflicting_rows > 0 {
+            return Err(anyhow!(
+                "{} rows changed since the transaction. No changes have been made.",
+                conflicting_rows
+            ));
+        }
+        let changes = kept;
+
+        let statements = changes
+            .iter()
+            .map(|change| change.to_sql())
+            .collect::<Result<Vec<_>>>()?;
+
+        if self.apply {
+            let mutations = changes
+                .iter()
+                .map(|change| change.mutation_count())
+                .sum::<usize>();
+            if mutations > MAX_MUTATIONS_PER_COMMIT {
+                return Err(anyhow!(
+                    "{} inverse changes ({} mutations) cannot be applied in one transaction.",
+                    changes.len(),
+                    mutations
+                ));
+            }
+            // Rows are read again in the transaction writing the inverse changes, so that a
+            // write landing after the reads above is not overwritten.
+            apply_if_unchanged(
+                self.client,
+                &expected,
+                changes.iter().map(|change| change.to_mutation()).collect(),
+            )
+            .await?;
+            info!("✅ Applied {} inverse changes", changes.len());
+        } else {
+            info!(
+                "ℹ️ {} inverse changes (dry run, use --apply to apply)",
+                changes.len()
+            );
+        }
+        Ok(statements)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use time::macros::datetime;
+
+    use super::invert;
+    use crate::changestream::{ColumnType, DataChangeRecord, Mod};
+
+    fn record(
+        record_sequence: &str,
+        mod_type: &str,
+        old_values: serde_json::Value,
+    ) -> DataChangeRecord {
+        DataChangeRecord {
+            commit_timestamp: datetime!(2023-03-01 23:34:43 UTC),
+            record_sequence: record_sequence.to_string(),
+            server_transaction_id: "txn".to_string(),
+            transaction_tag: "".to_string(),
+            is_system_transaction: false,
+            table_name: "Singers".to_string(),
+            column_types: vec![
+                ColumnType {
+                    name: "SingerId".to_string(),
+                    r#type: json!({"code": "INT64"}),
+                    is_primary_key: true,
+                },
+                ColumnType {
+                    name: "Name".to_string(),
+                    r#type: json!({"code": "STRING"}),
+                    is_primary_key: false,
+                },
+            ],
+            mod_type: mod_type.to_string(),
+            value_capture_type: "OLD_AND_NEW_VALUES".to_string(),
+            mods: vec![Mod {
+                keys: json!({"SingerId": "1"}),
+                old_values,
+            }],
+        }
+    }
+
+    /// Test that each modification is undone in reverse order of its record sequence.
+    #[test]
+    fn test_invert() {
+        let records = vec![
+            record("00000001", "UPDATE", json!({"Name": "a"})),
+            record("00000000", "INSERT", json!({})),
+            record("00000002", "DELETE", json!({"Name": "b"})),
+        ];
+
+        let changes = invert(&records).unwrap();
+        assert_eq!(
+            changes
+                .iter()
+                .map(|change| change.mutation_count())
+                .collect::<Vec<_>>(),
+            vec![2, 2, 1]
+        );
+        let statements = changes
+            .iter()
+            .map(|change| change.to_sql().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statements,
+            vec![
+                "INSERT INTO `Singers` (`SingerId`, `Name`) VALUES (1, 'b');",
+                "UPDATE `Singers` SET `Name` = 'a' WHERE `SingerId` = 1;",
+                "DELETE FROM `Singers` WHERE `SingerId` = 1;",
+            ]
+        );
+    }
+
+    /// Test that records without old values cannot be inverted.
+    #[test]
+    fn test_invert_requires_old_values() {
+        let mut record = record("00000000", "DELETE", json!({}));
+        record.value_capture_type = "NEW_VALUES".to_string();
+        assert!(invert(&[record]).is_err());
+    }
+}
@@ -1,12 +1,14 @@
 use std::fmt::Display;
 use std::num::ParseIntError;
 use std::ops::Deref;
+use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
 use async_recursion::async_recursion;
-use clap::{arg, Parser, Subcommand};
+use clap::error::ErrorKind;
+use clap::{arg, Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use futures::future::join_all;
 use google_cloud_default::WithAuthExt;
-use google_cloud_googleapis::spanner::admin::database::v1::GetDatabaseRequest;
 use google_cloud_spanner::admin::client::Client as AdminClient;
 use google_cloud_spanner::admin::AdminClientConfig;
 use google_cloud_spanner::client::{Client, ClientConfig};
@@ -18,42 +20,443 @@ use indicatif::ProgressBar;
 use log::{debug, error, info, trace, warn};
 use time::{error::Parse, ext::NumericalDuration, OffsetDateTime};
 
+use crate::audit::Auditor;
+use crate::backup::{BackupCreator, BackupOptions};
+use crate::changestream::ChangeStreamReader;
+use crate::commits::CommitFinder;
+use crate::ddl::{ddl_history, pre_drop_timestamp};
+use crate::diff::{DiffFormat, Differ};
+use crate::export::{ExportFormat, ExportSource, Exporter};
+use crate::invert::TransactionInverter;
+use crate::protect::{DropProtector, DEFAULT_RECORD};
+use crate::restore::DatabaseRestorer;
+use crate::retention::{
+    countdown, format_duration, parse_retention_period, Retention, RetentionUpdater,
+};
+use crate::revert::{ConflictPolicy, Reverter};
+use crate::schema::{quote_identifier, read_named_rows, DatabaseSchema};
+use crate::status::{ErrorOptions, QueryError};
+use crate::timeline::TimelineFinder;
+use crate::undelete::Undeleter;
+use crate::undo::UndoScriptGenerator;
+
+mod audit;
+mod backup;
+mod changestream;
+mod commits;
+mod ddl;
+mod diff;
+mod export;
+mod invert;
+mod operations;
+mod protect;
+mod restore;
+mod retention;
+mod revert;
+mod schema;
+mod status;
+mod timeline;
+mod undelete;
+mod undo;
+mod value;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Arguments {
     /// Google Cloud Project
     #[arg(short, long)]
     project: String,
-    /// Cloud Spanner Instance
+    /// Cloud Spanner Instance (optional for `audit --all-instances`)
     #[arg(short, long)]
-    instance: String,
-    /// Cloud Spanner Database
+    instance: Option<String>,
+    /// Cloud Spanner Database (optional for `audit`)
     #[arg(short, long)]
-    database: String,
+    database: Option<String>,
 
     /// Debug mode
     #[arg(long, action = clap::ArgAction::Count, default_value_t=0)]
     debug: u8,
     #[clap(subcommand)]
-    command: Command,
+    command: Scope,
+}
+
+/// Commands covering whole instances, and commands on a single database.
+#[derive(Debug, Subcommand)]
+enum Scope {
+    Audit {
+        /// Audit every instance in the project, rather than only the given instance
+        #[arg(long)]
+        all_instances: bool,
+    },
+    #[command(flatten)]
+    Database(Command),
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
     Query {
-        /// Spanner diagnostic query
+        #[command(flatten)]
+        search: SearchOptions,
+        /// Create a backup at the recovery timestamp once it has been found
+        #[arg(long)]
+        create_backup: bool,
+        /// Create a backup at the recovery timestamp if it will be garbage collected within
+        /// this time (in ms)
+        #[arg(long, value_parser=parse_duration, conflicts_with = "create_backup")]
+        backup_margin: Option<DisplayableDuration>,
+        /// Show a live countdown until the recovery timestamp is garbage collected, which runs
+        /// for up to the retention period of the database (up to 7 days) unless stopped with
+        /// Ctrl-C
+        #[arg(long)]
+        countdown: bool,
+        /// Enable drop protection on the database before searching, recording the previous
+        /// setting in the record file
+        #[arg(long)]
+        protect: bool,
+        /// File recording the previous setting, to restore with `unprotect`
+        #[arg(long, default_value = DEFAULT_RECORD, requires = "protect")]
+        record: PathBuf,
+        #[command(flatten)]
+        backup: BackupOptions,
+    },
+    Backup {
+        /// Version time of the backup
+        #[arg(short, long, value_parser=parse_timestamp)]
+        at: OffsetDateTime,
+        #[command(flatten)]
+        backup: BackupOptions,
+    },
+    Restore {
+        #[command(flatten)]
+        search: SearchOptions,
+        /// Recovery timestamp (optional, searched for using the query if not specified)
+        #[arg(long, value_parser=parse_timestamp)]
+        at: Option<OffsetDateTime>,
+        /// Cloud Spanner Instance to restore into (optional, defaults to the source instance)
+        #[arg(long)]
+        target_instance: Option<String>,
+        /// Name of the new Cloud Spanner Database to restore into
+        #[arg(long)]
+        target_database: String,
+        #[command(flatten)]
+        backup: BackupOptions,
+    },
+    Undelete {
+        /// Tables to restore deleted rows into, parent tables first
+        #[arg(short, long, required = true)]
+        table: Vec<String>,
+        /// SQL condition selecting the rows to restore
+        #[arg(short = 'w', long = "where", default_value = "TRUE")]
+        filter: String,
+        /// Historical read timestamp
+        #[arg(short, long, value_parser=parse_timestamp)]
+        at: OffsetDateTime,
+        /// Insert the missing rows (otherwise only report them)
+        #[arg(long)]
+        apply: bool,
+    },
+    Revert {
+        /// Table to revert values in
         #[arg(short, long)]
-        query: String,
-        /// Beginning of query window (optional)
+        table: String,
+        /// Columns to revert to their historical values
+        #[arg(short, long = "column", required = true)]
+        columns: Vec<String>,
+        /// SQL condition selecting the rows to revert
+        #[arg(short = 'w', long = "where", default_value = "TRUE")]
+        filter: String,
+        /// Historical read timestamp, before the incident
         #[arg(short, long, value_parser=parse_timestamp)]
-        start: Option<OffsetDateTime>,
-        /// End of query window (optional)
+        at: OffsetDateTime,
+        /// Timestamp after the incident, used to detect rows changed again since (required
+        /// unless `--conflict overwrite` is chosen)
+        #[arg(long, value_parser=parse_timestamp)]
+        incident_end: Option<OffsetDateTime>,
+        /// How to treat rows changed again since the incident
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Report)]
+        conflict: ConflictPolicy,
+        /// Write the historical values (otherwise only report them)
+        #[arg(long)]
+        apply: bool,
+    },
+    Export {
+        /// Tables to export
+        #[arg(short, long, required_unless_present = "query")]
+        table: Vec<String>,
+        /// Queries to export
+        #[arg(short, long)]
+        query: Vec<String>,
+        /// Read timestamp of the snapshot
+        #[arg(short, long, value_parser=parse_timestamp)]
+        at: OffsetDateTime,
+        /// Format of the exported files
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Csv)]
+        format: ExportFormat,
+        /// Directory to write the exported files and manifest to
+        #[arg(short, long, default_value = "export")]
+        output: PathBuf,
+    },
+    Diff {
+        /// Table to compare
+        #[arg(
+            short,
+            long,
+            required_unless_present = "query",
+            conflicts_with = "query"
+        )]
+        table: Option<String>,
+        /// Query to compare, instead of a table
+        #[arg(short, long, requires = "key")]
+        query: Option<String>,
+        /// Columns identifying each row of the query results
+        #[arg(short, long)]
+        key: Vec<String>,
+        /// SQL condition selecting the rows of the table to compare
+        #[arg(short = 'w', long = "where", default_value = "TRUE")]
+        filter: String,
+        /// Earlier read timestamp
+        #[arg(long, value_parser=parse_timestamp)]
+        from: OffsetDateTime,
+        /// Later read timestamp (optional, defaults to now)
+        #[arg(long, value_parser=parse_timestamp)]
+        to: Option<OffsetDateTime>,
+        /// Output format
+        #[arg(short, long, value_enum, default_value_t = DiffFormat::Table)]
+        format: DiffFormat,
+    },
+    UndoSql {
+        /// Table to return to its historical state
+        #[arg(short, long)]
+        table: String,
+        /// SQL condition selecting the rows to return to their historical state
+        #[arg(short = 'w', long = "where", default_value = "TRUE")]
+        filter: String,
+        /// Recovery timestamp
         #[arg(short, long, value_parser=parse_timestamp)]
-        end: Option<OffsetDateTime>,
-        /// Granularity
-        #[arg(short, long, value_parser=parse_duration, default_value_t=DisplayableDuration(10.milliseconds()))]
-        accuracy: DisplayableDuration,
+        at: OffsetDateTime,
+        /// File to write the script to (optional, defaults to standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    DdlHistory {
+        /// Maximum number of recent statements to list
+        #[arg(short, long, default_value_t = 20)]
+        limit: usize,
+    },
+    Schema {
+        /// Read timestamp of the schema to dump (optional, defaults to now)
+        #[arg(short, long, value_parser=parse_timestamp, conflicts_with = "from")]
+        at: Option<OffsetDateTime>,
+        /// Earlier read timestamp, to compare the schema between two timestamps
+        #[arg(long, value_parser=parse_timestamp)]
+        from: Option<OffsetDateTime>,
+        /// Later read timestamp (optional, defaults to now)
+        #[arg(long, value_parser=parse_timestamp, requires = "from")]
+        to: Option<OffsetDateTime>,
+    },
+    Timeline {
+        #[command(flatten)]
+        timeline: TimelineOptions,
+        /// Maximum number of changes to list
+        #[arg(short, long, default_value_t = 100)]
+        limit: usize,
+    },
+    Culprit {
+        #[command(flatten)]
+        search: SearchOptions,
+        /// Change stream to read the transactions inside the search result from
+        #[arg(long)]
+        change_stream: String,
     },
+    Invert {
+        /// Change stream with OLD_AND_NEW_VALUES capture to read the transaction from
+        #[arg(long)]
+        change_stream: String,
+        /// Commit timestamp of the transaction to undo
+        #[arg(
+            long,
+            value_parser=parse_timestamp,
+            required_unless_present = "transaction_id",
+            conflicts_with = "from"
+        )]
+        commit_timestamp: Option<OffsetDateTime>,
+        /// Server transaction ID of the transaction to undo
+        #[arg(long, requires = "from")]
+        transaction_id: Option<String>,
+        /// Beginning of the period to look for the transaction in
+        #[arg(long, value_parser=parse_timestamp)]
+        from: Option<OffsetDateTime>,
+        /// End of the period to look for the transaction in (optional, defaults to now)
+        #[arg(long, value_parser=parse_timestamp, requires = "from")]
+        to: Option<OffsetDateTime>,
+        /// File to write the inverse DML to for review (optional, defaults to standard output)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// How to treat rows changed again since the transaction
+        #[arg(long, value_enum, default_value_t = ConflictPolicy::Report)]
+        conflict: ConflictPolicy,
+        /// Apply the inverse changes in one transaction
+        #[arg(long)]
+        apply: bool,
+    },
+    Protect {
+        /// Protect every database in the instance, which also prevents the instance being
+        /// deleted
+        #[arg(long)]
+        all_databases: bool,
+        /// File recording the previous settings, to restore with `unprotect`
+        #[arg(long, default_value = DEFAULT_RECORD)]
+        record: PathBuf,
+    },
+    Unprotect {
+        /// Restore every database in the instance which is recorded, rather than only the
+        /// given database
+        #[arg(long)]
+        all_databases: bool,
+        /// File recording the settings to restore
+        #[arg(long, default_value = DEFAULT_RECORD)]
+        record: PathBuf,
+    },
+    Retention {
+        /// New version retention period, up to `7d` (optional, the current period is shown if
+        /// not specified)
+        #[arg(long, value_parser=parse_retention_period)]
+        set: Option<time::Duration>,
+        /// Allow the new retention period to be shorter than the current one, which garbage
+        /// collects older versions straight away
+        #[arg(long, requires = "set")]
+        allow_shorten: bool,
+        /// Apply the new retention period, rather than previewing the DDL statement
+        #[arg(long, requires = "set")]
+        apply: bool,
+    },
+}
+
+#[derive(Args, Debug, Clone)]
+struct SearchOptions {
+    /// Spanner diagnostic query
+    #[arg(short, long, required_unless_present = "dropped_table")]
+    query: Option<String>,
+    /// Dropped table to find the exact pre-drop timestamp of from the DDL history
+    #[arg(long)]
+    dropped_table: Option<String>,
+    /// Beginning of query window (optional)
+    #[arg(short, long, value_parser=parse_timestamp)]
+    start: Option<OffsetDateTime>,
+    /// End of query window (optional)
+    #[arg(short, long, value_parser=parse_timestamp)]
+    end: Option<OffsetDateTime>,
+    /// Granularity
+    #[arg(short, long, value_parser=parse_duration, default_value_t=DisplayableDuration(10.milliseconds()))]
+    accuracy: DisplayableDuration,
+    /// Direction of the transition to search for
+    #[arg(long, value_enum, default_value_t = Polarity::TrueToFalse)]
+    polarity: Polarity,
+    /// Number of timestamps to query concurrently in each round of the search
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=64))]
+    concurrency: u32,
+    /// Search backwards from the end of the window in exponentially growing steps, starting
+    /// with this step (in ms), before bisecting
+    #[arg(long, value_parser=parse_step)]
+    gallop: Option<DisplayableDuration>,
+    /// Suspected time of the transition, to check before searching the whole window
+    #[arg(long, value_parser=parse_timestamp)]
+    around: Option<OffsetDateTime>,
+    /// Uncertainty of the suspected time (in ms)
+    #[arg(long, value_parser=parse_duration, default_value_t=DisplayableDuration(600_000.milliseconds()))]
+    uncertainty: DisplayableDuration,
+    /// File of candidate timestamps, one per line, to check before searching the whole window
+    #[arg(long)]
+    candidates: Option<PathBuf>,
+    /// Table whose commit timestamp columns are used to snap the result to an exact commit
+    #[arg(long)]
+    snap_table: Vec<String>,
+    /// Change stream used to snap the result to an exact commit
+    #[arg(long)]
+    snap_change_stream: Option<String>,
+    /// Margin (in ms) kept after the garbage collection horizon, which advances during the
+    /// search
+    #[arg(long, value_parser=parse_duration, default_value_t=DisplayableDuration(60_000.milliseconds()))]
+    horizon_margin: DisplayableDuration,
+    #[command(flatten)]
+    errors: ErrorOptions,
+}
+
+impl SearchOptions {
+    /// Return the hinted timestamps to check first, from the suspected time and its
+    /// uncertainty, and from the candidates file.
+    fn hints(&self) -> Result<Vec<OffsetDateTime>> {
+        let mut hints = vec![];
+        if let Some(around) = self.around {
+            hints.push(around - *self.uncertainty);
+            hints.push(around + *self.uncertainty);
+        }
+        if let Some(path) = &self.candidates {
+            hints.extend(parse_candidates(&std::fs::read_to_string(path)?)?);
+        }
+        hints.sort();
+        hints.dedup();
+        Ok(hints)
+    }
+
+    /// Return the check query, which defaults to checking that the dropped table exists.
+    fn check_query(&self) -> String {
+        if let Some(query) = &self.query {
+            return query.clone();
+        }
+        // Clap requires --dropped-table whenever --query is not given.
+        let table = self.dropped_table.as_deref().unwrap_or_default();
+        format!("SELECT COUNT(*) >= 0 FROM {}", quote_identifier(table))
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+struct TimelineOptions {
+    /// Spanner query whose result is tracked over time
+    #[arg(short, long)]
+    query: String,
+    /// Beginning of query window (optional)
+    #[arg(short, long, value_parser=parse_timestamp)]
+    start: Option<OffsetDateTime>,
+    /// End of query window (optional)
+    #[arg(short, long, value_parser=parse_timestamp)]
+    end: Option<OffsetDateTime>,
+    /// Granularity
+    #[arg(short, long, value_parser=parse_duration, default_value_t=DisplayableDuration(10.milliseconds()))]
+    accuracy: DisplayableDuration,
+    /// Margin (in ms) kept after the garbage collection horizon, which advances while the
+    /// timeline is built
+    #[arg(long, value_parser=parse_duration, default_value_t=DisplayableDuration(60_000.milliseconds()))]
+    horizon_margin: DisplayableDuration,
+    #[command(flatten)]
+    errors: ErrorOptions,
+}
+
+/// Direction in which the check query changes at the point being searched for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Polarity {
+    /// Find the last moment good data was present, such as before rows were deleted
+    TrueToFalse,
+    /// Find the last moment before bad data appeared, such as a corrupt or duplicate row
+    FalseToTrue,
+}
+
+impl Polarity {
+    /// Return the value of the check query before the transition.
+    fn before(&self) -> bool {
+        *self == Polarity::TrueToFalse
+    }
+
+    /// Return the failures treated as `false`. Failures are only mapped to a value when
+    /// searching from `true` to `false`: in the other direction `false` is the value before
+    /// the transition, so a failure would be taken as good data and move the result later.
+    fn false_on<'a>(&self, errors: &'a ErrorOptions) -> &'a [QueryError] {
+        if self.before() {
+            &errors.false_on
+        } else {
+            &[]
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -95,82 +498,227 @@ impl ToOffsetDateTime for prost_types::Timestamp {
     }
 }
 
-/// Logic to find the closest timestamp at which the check query returns `true` in the
-/// first column of the first row.
+trait ToTimestamp {
+    fn to_timestamp(&self) -> prost_types::Timestamp;
+}
+
+impl ToTimestamp for OffsetDateTime {
+    fn to_timestamp(&self) -> prost_types::Timestamp {
+        prost_types::Timestamp {
+            seconds: self.unix_timestamp(),
+            nanos: self.nanosecond() as i32,
+        }
+    }
+}
+
+/// How soon before the garbage collection horizon reaches the last known good timestamp a
+/// warning is given to recover.
+const HORIZON_WARNING: time::Duration = time::Duration::minutes(10);
+
+/// The garbage collection horizon of a database, which advances with the clock as Spanner
+/// removes versions older than the retention period. It is extrapolated from the earliest
+/// version time when it was fetched, rather than fetching the database again.
+#[derive(Debug, Clone, Copy)]
+struct Horizon {
+    earliest_version_time: OffsetDateTime,
+    fetched: std::time::Instant,
+    margin: time::Duration,
+}
+
+impl Horizon {
+    /// Start extrapolating from an earliest version time which has just been fetched.
+    fn new(earliest_version_time: OffsetDateTime, margin: time::Duration) -> Self {
+        Horizon {
+            earliest_version_time,
+            fetched: std::time::Instant::now(),
+            margin,
+        }
+    }
+
+    /// Return the earliest version time now, extrapolated from when it was fetched.
+    fn earliest(&self) -> OffsetDateTime {
+        self.earliest_version_time + self.fetched.elapsed()
+    }
+
+    /// Return the earliest timestamp which is safe to read now, allowing for the margin.
+    fn earliest_safe(&self) -> OffsetDateTime {
+        self.earliest() + self.margin
+    }
+
+    /// Return the earliest safe timestamp if the horizon has overtaken the start of an
+    /// interval, or `None` if the start is still safe. Fails if the horizon has overtaken the
+    /// whole interval.
+    fn overtaken(
+        &self,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+    ) -> Result<Option<OffsetDateTime>> {
+        let earliest_safe = self.earliest_safe();
+        if earliest_safe <= *start {
+            return Ok(None);
+        }
+        if earliest_safe >= *end {
+            return Err(anyhow!(
+                "The transition before {} has fallen out of the version retention window.",
+                end
+            ));
+        }
+        Ok(Some(earliest_safe))
+    }
+}
+
+/// The outcome of running the check query at a timestamp. The outcome is unknown if the
+/// query failed, even after retrying.
+#[derive(Debug, Clone, PartialEq)]
+enum ProbeResult {
+    True,
+    False,
+    Unknown(String),
+}
+
+impl ProbeResult {
+    /// Return the value of the check query, if it is known.
+    fn value(&self) -> Option<bool> {
+        match self {
+            ProbeResult::True => Some(true),
+            ProbeResult::False => Some(false),
+            ProbeResult::Unknown(_) => None,
+        }
+    }
+}
+
+impl From<bool> for ProbeResult {
+    fn from(value: bool) -> Self {
+        if value {
+            ProbeResult::True
+        } else {
+            ProbeResult::False
+        }
+    }
+}
+
+impl Display for ProbeResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProbeResult::True => f.write_str("true"),
+            ProbeResult::False => f.write_str("false"),
+            ProbeResult::Unknown(e) => f.write_fmt(format_args!("unknown ({})", e)),
+        }
+    }
+}
+
+/// A query made during a search, with its outcome.
+#[derive(Debug, Clone)]
+struct Probe {
+    timestamp: OffsetDateTime,
+    result: ProbeResult,
+}
+
+/// The result of a timestamp search: an interval bracketing the transition of the check
+/// query, and every probe made to find it.
+#[derive(Debug, Clone)]
+struct SearchResult {
+    /// Latest timestamp known to return the value from before the transition
+    last_good: OffsetDateTime,
+    /// Earliest timestamp known to return the value from after the transition
+    first_bad: OffsetDateTime,
+    probes: Vec<Probe>,
+}
+
+impl SearchResult {
+    /// Return the width of the interval in which the transition happened.
+    fn uncertainty(&self) -> time::Duration {
+        self.first_bad - self.last_good
+    }
+
+    /// Log the bracket, and each probe at debug level.
+    fn log(&self) {
+        for probe in &self.probes {
+            debug!("Probe at {}: {}", probe.timestamp, probe.result);
+        }
+        info!("✅ Last known good timestamp: {}", self.last_good);
+        info!("ℹ️ First known bad timestamp: {}", self.first_bad);
+        info!(
+            "ℹ️ Uncertainty: {}ms after {} probes",
+            self.uncertainty().whole_milliseconds(),
+            self.probes.len()
+        );
+    }
+}
+
+/// Logic to find the closest timestamp before the check query changes value in the first
+/// column of the first row, from `true` to `false` or the reverse depending on the polarity.
 struct TimestampFinder {
     start: OffsetDateTime,
     end: OffsetDateTime,
     accuracy: time::Duration,
     query: String,
+    polarity: Polarity,
+    concurrency: u32,
+    gallop: Option<time::Duration>,
+    hints: Vec<OffsetDateTime>,
+    errors: ErrorOptions,
+    horizon: Option<Horizon>,
     client: Client,
 }
 
 impl TimestampFinder {
-    /// Run a Spanner query at a specific timestamp.
-    async fn query_at(&self, ts: &OffsetDateTime) -> Result<bool> {
-        let mut tx = self
-            .client
-            .single_with_timestamp_bound(TimestampBound::read_timestamp(Timestamp {
-                seconds: ts.unix_timestamp(),
-                nanos: ts.nanosecond() as i32,
-            }))
+    /// Run a Spanner query at a specific timestamp, retrying failures with exponential
+    /// backoff as configured. The outcome is unknown if a failure persists, or is neither
+    /// retried nor fatal.
+    async fn query_at(&self, ts: &OffsetDateTime) -> Result<ProbeResult> {
+        let outcome = self
+            .errors
+            .retry(ts, || {
+                check_query(
+                    &self.client,
+                    read_timestamp(ts),
+                    &self.query,
+                    &self.errors,
+                    self.polarity,
+                )
+            })
             .await?;
+        Ok(match outcome {
+            Ok(value) => value.into(),
+            Err((_, e)) => ProbeResult::Unknown(e.to_string()),
+        })
+    }
 
-        match tx.query(Statement::new(&self.query)).await {
-            Ok(mut rows) => match rows.next().await {
-                Ok(Some(row)) => row
-                    .column::<bool>(0)
-                    .map_err(|e| anyhow!(format!("column error: {e}"))),
-                Ok(None) => Ok(false),
-                Err(status) if status.message().contains("Table not found") => Ok(false),
-                Err(status)
-                    if status
-                        .message()
-                        .contains("exceeded the maximum timestamp staleness") =>
-                {
-                    warn!("{}", status.message());
-                    Ok(false)
-                }
-                Err(status) => Err(status.into()),
-            },
-            // Don't treat a table not being found as a fatal error. Often required when
-            // recovering from DDL errors, such as dropping tables.
-            Err(status) if status.message().contains("Table not found") => Ok(false),
-            // Treat this as a soft error and continue processing.
-            Err(status)
-                if status
-                    .message()
-                    .contains("exceeded the maximum timestamp staleness") =>
-            {
-                warn!("{}", status.message());
-                Ok(false)
+    /// Run a Spanner query at a specific timestamp, failing if the outcome is unknown.
+    async fn decide_at(&self, ts: &OffsetDateTime) -> Result<bool> {
+        match self.query_at(ts).await? {
+            ProbeResult::Unknown(e) => {
+                Err(anyhow!("Check query could not be decided at {}: {}", ts, e))
             }
-            Err(status) => Err(status.into()),
+            result => Ok(result == ProbeResult::True),
         }
     }
 
-    /// Find the latest timestamp at which the database query returns `true`
-    /// in the first column of the first row.
+    /// Narrow an interval which starts before the transition and ends after it, until it is
+    /// narrower than the accuracy, recording every probe made. Each round probes evenly
+    /// spaced timestamps within the interval concurrently, and narrows it to the
+    /// sub-interval in which the value changes.
     #[async_recursion]
     async fn find_timestamp<I>(
         &self,
         start: &OffsetDateTime,
         end: &OffsetDateTime,
         remaining_iterations: u32,
-        increment_progress: I,
-    ) -> Result<OffsetDateTime>
+        probes: &mut Vec<Probe>,
+        increment_progress: &I,
+    ) -> Result<(OffsetDateTime, OffsetDateTime)>
     where
-        I: std::marker::Send
+        I: std::marker::Sync
             + Fn(OffsetDateTime, OffsetDateTime, OffsetDateTime, bool) -> Result<()>,
     {
-        let midpoint = Self::timestamp_midpoint(start, end);
-        debug!("Querying between {} and {} at {}...", start, end, midpoint);
+        // Spanner keeps garbage collecting old versions while the search runs.
+        let start = &self.ahead_of_horizon(start, end, probes).await?;
 
-        // Error if the search interval is exhausted (or start >= end)
-        if (*end - *start) <= 0.seconds() {
-            return Err(anyhow!(
-                "Maximum accuracy reached without finding a result."
-            ));
+        // Successfully found an interval within the accuracy, or one that cannot be split.
+        if (*end - *start) < self.accuracy || (*end - *start) <= 1.nanoseconds() {
+            trace!("  Transition found between {} and {}", start, end);
+            return Ok((*start, *end));
         }
 
         // Error if there are no more iterations
@@ -180,91 +728,315 @@ impl TimestampFinder {
             ));
         }
 
-        let result = match self.query_at(&midpoint).await {
-            Ok(true) => {
-                increment_progress(*start, *end, midpoint, true)?;
-                if (*end - midpoint) < self.accuracy {
-                    // Successfully found a timestamp within the accuracy interval.
-                    trace!("  Query succeeded. Closest timestamp found: {}", midpoint);
-                    Ok(midpoint)
-                } else {
-                    // Query succeeded, but not yet accurate enough. Search later.
-                    trace!("  Query succeeded (not within accuracy window). Searching later.");
-                    self.find_timestamp(
-                        &midpoint,
-                        end,
-                        remaining_iterations - 1,
-                        increment_progress,
-                    )
-                    .await
+        let timestamps = Self::probe_timestamps(start, end, self.concurrency);
+        debug!(
+            "Querying between {} and {} at {} timestamps...",
+            start,
+            end,
+            timestamps.len()
+        );
+        let results = join_all(timestamps.iter().map(|ts| self.query_at(ts)))
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>>>()?;
+
+        // The interval narrows to end at the first probe after the transition.
+        let mut narrowed = (*start, *end);
+        let mut transition_found = false;
+        let mut undecided = 0;
+        for (ts, result) in timestamps.into_iter().zip(results) {
+            let value = result.value();
+            match value {
+                Some(value) => increment_progress(*start, *end, ts, value)?,
+                None => {
+                    error!("  Query could not be decided at {} ({}).", ts, result);
+                    undecided += 1;
                 }
             }
-            Ok(false) => {
-                // Query failed. Search earlier.
-                trace!("  Query failed. Searching earlier.");
-                increment_progress(*start, *end, midpoint, false)?;
-                self.find_timestamp(
-                    start,
-                    &midpoint,
-                    remaining_iterations - 1,
-                    increment_progress,
-                )
-                .await
-            }
-            Err(e) => {
-                // Log error and search earlier.
-                error!("  Query failed ({}). Searching earlier.", e);
-                self.find_timestamp(
-                    start,
-                    &midpoint,
-                    remaining_iterations - 1,
-                    increment_progress,
-                )
-                .await
+            probes.push(Probe {
+                timestamp: ts,
+                result,
+            });
+
+            if transition_found {
+                continue;
             }
-        };
+            match value {
+                Some(value) if value == self.polarity.before() => {
+                    // Query succeeded. Search later.
+                    trace!("  Query succeeded at {}.", ts);
+                    narrowed.0 = ts;
+                }
+                Some(_) => {
+                    // Query failed. Search earlier.
+                    trace!("  Query failed at {}. Searching earlier.", ts);
+                    narrowed.1 = ts;
+                    transition_found = true;
+                }
+                // Never narrow past an undecided probe, which could be on either side of the
+                // transition.
+                None => {}
+            }
+        }
 
-        match result {
-            Ok(ts) => Ok(ts),
-            Err(e) => Err(e),
+        if narrowed == (*start, *end) {
+            return Err(anyhow!(
+                "No probe between {} and {} could be decided.",
+                start,
+                end
+            ));
         }
+
+        // An undecided probe narrows the interval less than expected, so allow another round
+        // for each one rather than exhausting the iterations early.
+        self.find_timestamp(
+            &narrowed.0,
+            &narrowed.1,
+            remaining_iterations - 1 + undecided,
+            probes,
+            increment_progress,
+        )
+        .await
     }
 
-    /// Check that the query returns `true` at the beginning of the period and
-    /// `false` at the end of the period.
-    async fn check_bounds(&self) -> Result<()> {
-        if self.query_at(&self.end).await? {
+    /// Move the start of an interval forward if the garbage collection horizon has overtaken
+    /// it, checking that the query still returns its value from before the transition there.
+    async fn ahead_of_horizon(
+        &self,
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        probes: &mut Vec<Probe>,
+    ) -> Result<OffsetDateTime> {
+        let Some(earliest_safe) = (match &self.horizon {
+            Some(horizon) => horizon.overtaken(start, end)?,
+            None => None,
+        }) else {
+            return Ok(*start);
+        };
+
+        warn!(
+            "Garbage collection is about to reach {}. Moving the start of the search to {}.",
+            start, earliest_safe
+        );
+        let value = self.decide_at(&earliest_safe).await?;
+        probes.push(Probe {
+            timestamp: earliest_safe,
+            result: value.into(),
+        });
+        if value != self.polarity.before() {
             return Err(anyhow!(
-                "Check query returned `true` at the end of the time window."
+                "Check query returned `{}` at {}. The transition has fallen out of the version \
+                 retention window.",
+                value,
+                earliest_safe
             ));
         }
+        Ok(earliest_safe)
+    }
 
-        if !self.query_at(&self.start).await? {
+    /// Check that the query returns the opposite of its value from before the transition at
+    /// the end of the period.
+    async fn check_end(&self, probes: &mut Vec<Probe>) -> Result<()> {
+        let before = self.polarity.before();
+
+        let end_value = self.decide_at(&self.end).await?;
+        probes.push(Probe {
+            timestamp: self.end,
+            result: end_value.into(),
+        });
+        if end_value == before {
             return Err(anyhow!(
-                "Check query returned `false` at the start of the time window."
+                "Check query returned `{}` at the end of the time window.",
+                before
             ));
         }
 
         Ok(())
     }
 
+    /// Return the start of the period, or the earliest timestamp safe from garbage
+    /// collection if that is later.
+    fn earliest_start(&self) -> OffsetDateTime {
+        match &self.horizon {
+            Some(horizon) => self.start.max(horizon.earliest_safe()),
+            None => self.start,
+        }
+    }
+
+    /// Check that the query returns its value from before the transition at the beginning
+    /// of the period, or the earliest timestamp safe from garbage collection if that is
+    /// later. Returns the timestamp checked.
+    async fn check_start(&self, probes: &mut Vec<Probe>) -> Result<OffsetDateTime> {
+        let before = self.polarity.before();
+
+        let start = self.earliest_start();
+        if start >= self.end {
+            return Err(anyhow!(
+                "The time window has fallen out of the version retention window."
+            ));
+        }
+        info!("❔ Checking query at start ({}) timestamp...", start);
+        let start_value = self.decide_at(&start).await?;
+        probes.push(Probe {
+            timestamp: start,
+            result: start_value.into(),
+        });
+        if start_value != before {
+            return Err(anyhow!(
+                "Check query returned `{}` at the start of the time window.",
+                !before
+            ));
+        }
+
+        Ok(start)
+    }
+
+    /// Search backwards from a timestamp after the transition in exponentially growing steps,
+    /// until the query returns its value from before the transition. Returns that timestamp,
+    /// if one was found before reaching the start of the window or the garbage collection
+    /// horizon, and the earliest timestamp known to be after the transition.
+    async fn gallop(
+        &self,
+        step: time::Duration,
+        end: &OffsetDateTime,
+        probes: &mut Vec<Probe>,
+    ) -> Result<(Option<OffsetDateTime>, OffsetDateTime)> {
+        let mut first_bad = *end;
+        let mut step = step;
+
+        loop {
+            // A step reaching past the representable range is past the start of the window.
+            let Some(ts) = end
+                .checked_sub(step)
+                .filter(|ts| *ts > self.earliest_start())
+            else {
+                return Ok((None, first_bad));
+            };
+
+            debug!(
+                "Querying {}ms before the end at {}...",
+                step.whole_milliseconds(),
+                ts
+            );
+            let result = self.query_at(&ts).await?;
+            match result.value() {
+                Some(value) if value == self.polarity.before() => {
+                    trace!("  Query succeeded. Bisecting from here.");
+                    probes.push(Probe {
+                        timestamp: ts,
+                        result,
+                    });
+                    return Ok((Some(ts), first_bad));
+                }
+                Some(_) => {
+                    trace!("  Query failed. Searching earlier.");
+                    first_bad = ts;
+                }
+                None => error!(
+                    "  Query could not be decided ({}). Searching earlier.",
+                    result
+                ),
+            }
+            probes.push(Probe {
+                timestamp: ts,
+                result,
+            });
+            let Some(next) = step.checked_mul(2) else {
+                return Ok((None, first_bad));
+            };
+            step = next;
+        }
+    }
+
+    /// Check the query at each hinted timestamp within the window and ahead of the garbage
+    /// collection horizon in order, until one returns
+    /// the value from after the transition. Returns the latest hint before the transition, if
+    /// there is one, and the earliest timestamp known to be after it. Hints at which the
+    /// query cannot be decided are skipped.
+    async fn check_hints(
+        &self,
+        probes: &mut Vec<Probe>,
+    ) -> Result<(Option<OffsetDateTime>, OffsetDateTime)> {
+        let mut last_good = None;
+        for ts in self
+            .hints
+            .iter()
+            .filter(|ts| **ts > self.earliest_start() && **ts < self.end)
+        {
+            debug!("Querying at hinted timestamp {}...", ts);
+            let result = self.query_at(ts).await?;
+            let value = result.value();
+            if value.is_none() {
+                error!("  Query could not be decided ({}).", result);
+            }
+            probes.push(Probe {
+                timestamp: *ts,
+                result,
+            });
+            match value {
+                Some(value) if value == self.polarity.before() => last_good = Some(*ts),
+                Some(_) => return Ok((last_good, *ts)),
+                None => {}
+            }
+        }
+        Ok((last_good, self.end))
+    }
+
     /// Execute the timestamp finder.
-    async fn run(&self) -> Result<OffsetDateTime> {
-        info!(
-            "❔ Checking query at start ({}) and end ({}) timestamps...",
-            self.start, self.end
-        );
+    async fn run(&mut self) -> Result<SearchResult> {
+        // A name which cannot be resolved at either end of the window is a mistake in the
+        // query rather than a column which was dropped.
+        let timestamps = [self.end, self.earliest_start()];
+        self.errors
+            .resolve_names(&timestamps, |ts| {
+                read_named_rows(&self.client, read_timestamp(&ts), &self.query)
+            })
+            .await;
 
-        self.check_bounds().await?;
+        info!("❔ Checking query at end ({}) timestamp...", self.end);
+        let mut probes = vec![];
+        self.check_end(&mut probes).await?;
 
-        let bar = ProgressBar::new(self.expected_queries().into());
+        let (hinted_start, end) = if self.hints.is_empty() {
+            (None, self.end)
+        } else {
+            info!(
+                "❔ Checking query at {} hinted timestamps...",
+                self.hints.len()
+            );
+            self.check_hints(&mut probes).await?
+        };
+
+        if !self.hints.is_empty() && hinted_start.is_none() {
+            warn!("No hinted timestamp is before the transition. Searching the whole window.");
+        }
+
+        // Galloping avoids probing the start of the window, close to the garbage collection
+        // horizon, unless the transition is not found before reaching it.
+        let (start, end) = match (hinted_start, self.gallop) {
+            (Some(start), _) => {
+                info!("✅ Hints narrowed the search to {} - {}", start, end);
+                (start, end)
+            }
+            (None, Some(step)) => {
+                info!("❔ Searching backwards from {}...", end);
+                match self.gallop(step, &end, &mut probes).await? {
+                    (Some(last_good), first_bad) => (last_good, first_bad),
+                    (None, first_bad) => (self.check_start(&mut probes).await?, first_bad),
+                }
+            }
+            (None, None) => (self.check_start(&mut probes).await?, end),
+        };
+
+        let bar = ProgressBar::new(self.expected_queries(&start, &end).into());
         info!("❔ Searching for closest recovery timestamp...");
-        let ts = self
+        let bracket = self
             .find_timestamp(
-                &self.start,
-                &self.end,
-                self.expected_queries(),
-                |start, end, mp, res| {
+                &start,
+                &end,
+                self.expected_rounds(&start, &end),
+                &mut probes,
+                &|start, end, mp, res| {
                     bar.set_message(format!("{} - {} - {} ({})", start, mp, end, res));
                     bar.inc(1);
                     Ok(())
@@ -273,18 +1045,128 @@ impl TimestampFinder {
             .await;
 
         bar.finish();
-        ts
+        let (last_good, first_bad) = bracket?;
+        if let Some(horizon) = &self.horizon {
+            let remaining = last_good - horizon.earliest();
+            if remaining < HORIZON_WARNING {
+                warn!(
+                    "Garbage collection reaches {} in {}s. Recover or back up now.",
+                    last_good,
+                    remaining.whole_seconds()
+                );
+            }
+        }
+        Ok(SearchResult {
+            last_good,
+            first_bad,
+            probes,
+        })
+    }
+
+    /// Narrow the bracket to a single commit, by checking the query at each commit within it
+    /// in order. The first commit at which the query changes is the bad commit, and the
+    /// recovery timestamp becomes the nanosecond before it.
+    async fn snap_to_commit(
+        &self,
+        mut result: SearchResult,
+        commits: &[OffsetDateTime],
+    ) -> Result<SearchResult> {
+        info!(
+            "❔ Checking {} commits between {} and {}...",
+            commits.len(),
+            result.last_good,
+            result.first_bad
+        );
+        for commit in commits
+            .iter()
+            .filter(|ts| **ts > result.last_good && **ts <= result.first_bad)
+        {
+            let value = self.decide_at(commit).await?;
+            result.probes.push(Probe {
+                timestamp: *commit,
+                result: value.into(),
+            });
+            if value == self.polarity.before() {
+                result.last_good = *commit;
+            } else {
+                info!(
+                    "✅ Snapped to the bad commit at {}, recovering to 1ns before it",
+                    commit
+                );
+                result.last_good = *commit - 1.nanoseconds();
+                result.first_bad = *commit;
+                return Ok(result);
+            }
+        }
+
+        warn!("No commit found at which the check query changes. Keeping the search result.");
+        Ok(result)
     }
 
-    /// Calculate the number of timeline checks expected.
-    fn expected_queries(&self) -> u32 {
-        ((self.end - self.start).whole_nanoseconds() / self.accuracy.whole_nanoseconds()).ilog2()
+    /// Calculate the number of search rounds expected between two timestamps.
+    fn expected_rounds(&self, start: &OffsetDateTime, end: &OffsetDateTime) -> u32 {
+        ((*end - *start).whole_nanoseconds() / self.accuracy.whole_nanoseconds())
+            .max(1)
+            .ilog(self.concurrency as i128 + 1)
             + 2
     }
 
-    // Calculate the mid-point of two timestamps.
-    fn timestamp_midpoint(start: &OffsetDateTime, end: &OffsetDateTime) -> OffsetDateTime {
-        start.saturating_add((*end - *start) / 2)
+    /// Calculate the number of timeline checks expected between two timestamps.
+    fn expected_queries(&self, start: &OffsetDateTime, end: &OffsetDateTime) -> u32 {
+        self.expected_rounds(start, end) * self.concurrency
+    }
+
+    // Calculate evenly spaced timestamps dividing an interval into `concurrency + 1` parts.
+    fn probe_timestamps(
+        start: &OffsetDateTime,
+        end: &OffsetDateTime,
+        concurrency: u32,
+    ) -> Vec<OffsetDateTime> {
+        let step = ((*end - *start) / (concurrency + 1)).max(1.nanoseconds());
+        (1..=concurrency)
+            .map(|i| start.saturating_add(step * i))
+            .filter(|ts| ts > start && ts < end)
+            .collect()
+    }
+}
+
+/// Return a timestamp bound reading at exactly the given timestamp.
+fn read_timestamp(ts: &OffsetDateTime) -> TimestampBound {
+    TimestampBound::read_timestamp(Timestamp {
+        seconds: ts.unix_timestamp(),
+        nanos: ts.nanosecond() as i32,
+    })
+}
+
+/// Run a check query with the given timestamp bound, returning the boolean value in the
+/// first column of the first row. Failures which count as `false` for the polarity are
+/// treated as `false`, such as a table not being found when recovering from DDL changes
+/// like dropping tables.
+async fn check_query(
+    client: &Client,
+    bound: TimestampBound,
+    query: &str,
+    errors: &ErrorOptions,
+    polarity: Polarity,
+) -> Result<bool> {
+    let result = async {
+        let mut tx = client.single_with_timestamp_bound(bound).await?;
+        let mut rows = tx.query(Statement::new(query)).await?;
+        match rows.next().await? {
+            Some(row) => row
+                .column::<bool>(0)
+                .map_err(|e| anyhow!(format!("column error: {e}"))),
+            None => Ok(false),
+        }
+    }
+    .await;
+
+    match result {
+        Err(e) if polarity.false_on(errors).contains(&errors.classify(&e)) => {
+            debug!("Treating failure as `false`: {}", e);
+            Ok(false)
+        }
+        result => result,
     }
 }
 
@@ -293,6 +1175,18 @@ fn parse_timestamp(ts: &str) -> Result<OffsetDateTime, Parse> {
     OffsetDateTime::parse(ts, &time::format_description::well_known::Rfc3339)
 }
 
+/// Parse candidate timestamps, one per line, ignoring blank lines and `#` comments.
+fn parse_candidates(text: &str) -> Result<Vec<OffsetDateTime>> {
+    text.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            parse_timestamp(line)
+                .map_err(|e| anyhow!("Invalid candidate timestamp {}: {}", line, e))
+        })
+        .collect()
+}
+
 /// Parse a duration from a number of milliseconds.
 fn parse_duration(millis: &str) -> Result<DisplayableDuration, ParseIntError> {
     Ok(DisplayableDuration(time::Duration::milliseconds(
@@ -300,6 +1194,17 @@ fn parse_duration(millis: &str) -> Result<DisplayableDuration, ParseIntError> {
     )))
 }
 
+/// Parse a step in milliseconds, which must be positive to move away from where it starts.
+fn parse_step(millis: &str) -> Result<DisplayableDuration> {
+    let step = parse_duration(millis)?;
+    if *step <= time::Duration::ZERO {
+        return Err(anyhow!(
+            "The step must be a positive number of milliseconds."
+        ));
+    }
+    Ok(step)
+}
+
 /// Return the current time of the database server.
 async fn database_time(client: &Client) -> Result<OffsetDateTime> {
     let mut tx = client.single().await?;
@@ -316,6 +1221,92 @@ async fn database_time(client: &Client) -> Result<OffsetDateTime> {
     }
 }
 
+/// Connect to the specified Spanner database.
+async fn connect(database: &str) -> Result<Client> {
+    let cfg = ClientConfig::default().with_auth().await?;
+    Ok(Client::new(database, cfg).await?)
+}
+
+/// Search for the closest recovery timestamp using the command line search options.
+async fn find_recovery_timestamp(
+    admin_client: &AdminClient,
+    instance: &str,
+    database: &str,
+    search: SearchOptions,
+) -> Result<SearchResult> {
+    search.errors.check()?;
+    let earliest_time = Retention::fetch(admin_client, database)
+        .await?
+        .log()
+        .earliest_version_time;
+
+    if let Some(table) = &search.dropped_table {
+        info!("❔ Checking DDL history for DROP TABLE {}...", table);
+        match pre_drop_timestamp(admin_client, instance, database, table).await? {
+            Some(target) if target < earliest_time => {
+                return Err(anyhow!(
+                    "The pre-drop timestamp {} is before the earliest version time {}, so it has been garbage collected.",
+                    target,
+                    earliest_time
+                ));
+            }
+            Some(target)
+                if search.start.is_some_and(|start| target < start)
+                    || search.end.is_some_and(|end| target > end) =>
+            {
+                warn!(
+                    "The pre-drop timestamp {} is outside --start/--end. Searching for the timestamp instead.",
+                    target
+                );
+            }
+            Some(target) => {
+                info!("✅ Found exact pre-drop recovery timestamp: {}", target);
+                return Ok(SearchResult {
+                    last_good: target,
+                    first_bad: target + 1.nanoseconds(),
+                    probes: vec![],
+                });
+            }
+            None => warn!(
+                "No DROP TABLE {} found in the DDL history. Searching for the timestamp instead.",
+                table
+            ),
+        }
+    }
+
+    let horizon = Horizon::new(earliest_time, *search.horizon_margin);
+    let client = connect(database).await?;
+    let database_time = database_time(&client).await?;
+
+    let mut finder = TimestampFinder {
+        start: search.start.unwrap_or(earliest_time),
+        end: search.end.unwrap_or(database_time),
+        accuracy: *search.accuracy,
+        query: search.check_query(),
+        polarity: search.polarity,
+        concurrency: search.concurrency,
+        gallop: search.gallop.map(|step| *step),
+        hints: search.hints()?,
+        errors: search.errors.clone(),
+        horizon: Some(horizon),
+        client,
+    };
+
+    let mut result = finder.run().await?;
+    if !search.snap_table.is_empty() || search.snap_change_stream.is_some() {
+        let commits = CommitFinder {
+            client: &finder.client,
+            tables: search.snap_table.clone(),
+            change_stream: search.snap_change_stream.clone(),
+        }
+        .run(&result.last_good, &result.first_bad)
+        .await?;
+        result = finder.snap_to_commit(result, &commits).await?;
+    }
+    result.log();
+    Ok(result)
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = Arguments::parse();
@@ -329,62 +1320,554 @@ async fn main() -> Result<()> {
         })
         .init();
 
+    // Clap cannot require the instance and database for some subcommands only, so they are
+    // checked here, before authenticating.
+    match args.command {
+        Scope::Audit { all_instances } => {
+            let instance = (!all_instances).then(|| required(&args.instance, "instance"));
+            let admin_client =
+                AdminClient::new(AdminClientConfig::default().with_auth().await?).await?;
+            let audits = Auditor {
+                admin_client: &admin_client,
+                project: args.project.clone(),
+                instance: instance.map(|id| format!("projects/{}/instances/{}", args.project, id)),
+            }
+            .run()
+            .await?;
+            for audit in audits {
+                println!("{}", audit.summary()?);
+            }
+            Ok(())
+        }
+        Scope::Database(command) => {
+            let instance_id = required(&args.instance, "instance");
+            let database_id = required(&args.database, "database");
+            run(args.project, instance_id, database_id, command).await
+        }
+    }
+}
+
+/// Return the value of an argument the command requires, exiting with a usage error as clap
+/// does if it is missing.
+fn required(value: &Option<String>, name: &str) -> String {
+    value.clone().unwrap_or_else(|| {
+        Arguments::command()
+            .error(
+                ErrorKind::MissingRequiredArgument,
+                format!("--{} is required by this command", name),
+            )
+            .exit()
+    })
+}
+
+/// Run a command on a single database.
+async fn run(
+    project: String,
+    instance_id: String,
+    database_id: String,
+    command: Command,
+) -> Result<()> {
     // Connect to database.
-    let cfg = ClientConfig::default().with_auth().await?;
     let admin_cfg = AdminClientConfig::default().with_auth().await?;
     let admin_client = AdminClient::new(admin_cfg).await?;
 
-    let database = format!(
-        "projects/{}/instances/{}/databases/{}",
-        args.project, args.instance, args.database
-    );
+    let instance = format!("projects/{}/instances/{}", project, instance_id);
+    let database = format!("{}/databases/{}", instance, database_id);
     info!("ℹ️ Connecting to database: {}", database);
 
-    match args.command {
+    match command {
         Command::Query {
-            query,
-            start,
-            end,
-            accuracy,
+            search,
+            create_backup,
+            backup_margin,
+            countdown: show_countdown,
+            protect,
+            record,
+            backup,
         } => {
-            let database_info = admin_client
-                .database()
-                .get_database(
-                    GetDatabaseRequest {
-                        name: database.clone(),
-                    },
-                    None,
-                )
+            if protect {
+                DropProtector {
+                    admin_client: &admin_client,
+                    instance: instance.clone(),
+                    database: database.clone(),
+                    all_databases: false,
+                    record,
+                }
+                .protect()
+                .await?;
+            }
+            let target = find_recovery_timestamp(&admin_client, &instance, &database, search)
                 .await?
-                .into_inner();
-            let earliest_time = &database_info
-                .earliest_version_time
-                .unwrap()
-                .to_offset_date_time();
-
-            let retention_period = &database_info.version_retention_period;
-
-            info!("⏱️ Earliest recovery time: {}", &earliest_time);
-            info!("⏱️ Retention period: {}", &retention_period);
-            let client = Client::new(database.clone(), cfg).await?;
-            let database_time = database_time(&client).await?;
-
-            let finder = TimestampFinder {
-                start: start.unwrap_or(*earliest_time),
-                end: end.unwrap_or(database_time),
-                accuracy: *accuracy,
-                query,
-                client,
+                .last_good;
+
+            let expiry = Retention::fetch(&admin_client, &database)
+                .await?
+                .expiry(&target)
+                .ok_or_else(|| anyhow!("{} has already been garbage collected.", target))?;
+            let remaining = expiry - OffsetDateTime::now_utc();
+            info!(
+                "⏱️ Recovery timestamp remains readable for {} (until {})",
+                format_duration(remaining),
+                expiry
+            );
+            let create_backup = match backup_margin {
+                Some(margin) if remaining < *margin => {
+                    warn!(
+                        "Less than {} remains to recover. Backing up automatically.",
+                        format_duration(*margin)
+                    );
+                    true
+                }
+                _ => create_backup,
             };
 
-            let target = finder.run().await?;
-            info!("✅ Found closest recovery timestamp: {}", target);
-            info!("ℹ️ To back up a database at this point in time:");
-            info!("ℹ️   gcloud spanner backups create {} --instance={} --database={} --expiration-date={} --async",
-                    format!("backup-{}", uuid::Uuid::new_v4().simple()), &args.instance, &args.database, &target.format(&time::format_description::well_known::Rfc3339)?);
+            if create_backup {
+                BackupCreator {
+                    admin_client: &admin_client,
+                    instance: instance.clone(),
+                    database: database.clone(),
+                    backup_id: backup.backup_id(),
+                    version_time: target,
+                    expiration: backup.expiration(),
+                }
+                .run()
+                .await?;
+            } else {
+                info!("ℹ️ To back up a database at this point in time:");
+                info!("ℹ️   gcloud spanner backups create {} --instance={} --database={} --version-time={} {} --async",
+                        backup.backup_id(), &instance_id, &database_id, &target.format(&time::format_description::well_known::Rfc3339)?, backup.expiration().gcloud_option()?);
+            }
             info!("ℹ️ To execute a query at this point in time:");
-            info!("ℹ️   gcloud spanner databases execute-sql {} --project={} --instance={} --sql='SELECT true' --read-timestamp={}", &args.database, &args.project, &args.instance,
+            info!("ℹ️   gcloud spanner databases execute-sql {} --project={} --instance={} --sql='SELECT true' --read-timestamp={}", &database_id, &project, &instance_id,
                     &target.format(&time::format_description::well_known::Rfc3339)?);
+            if show_countdown {
+                countdown(&target, &expiry).await;
+            }
+        }
+        Command::Backup { at, backup } => {
+            BackupCreator {
+                admin_client: &admin_client,
+                instance: instance.clone(),
+                database: database.clone(),
+                backup_id: backup.backup_id(),
+                version_time: at,
+                expiration: backup.expiration(),
+            }
+            .run()
+            .await?;
+        }
+        Command::Restore {
+            search,
+            at,
+            target_instance,
+            target_database,
+            backup,
+        } => {
+            let target = match at {
+                Some(ts) => ts,
+                None => {
+                    find_recovery_timestamp(&admin_client, &instance, &database, search.clone())
+                        .await?
+                        .last_good
+                }
+            };
+
+            let backup = BackupCreator {
+                admin_client: &admin_client,
+                instance: instance.clone(),
+                database: database.clone(),
+                backup_id: backup.backup_id(),
+                version_time: target,
+                expiration: backup.expiration(),
+            }
+            .run()
+            .await?;
+
+            let target_instance = format!(
+                "projects/{}/instances/{}",
+                project,
+                target_instance.unwrap_or(instance_id)
+            );
+            let restored = DatabaseRestorer {
+                admin_client: &admin_client,
+                instance: target_instance,
+                database_id: target_database,
+                backup: backup.name,
+            }
+            .run()
+            .await?;
+
+            info!("❔ Verifying check query against restored database...");
+            let client = connect(&restored.name).await?;
+            let expected = search.polarity.before();
+            if check_query(
+                &client,
+                TimestampBound::strong_read(),
+                &search.check_query(),
+                &search.errors,
+                search.polarity,
+            )
+            .await?
+                == expected
+            {
+                info!(
+                    "✅ Check query returned `{}` against {}",
+                    expected, restored.name
+                );
+            } else {
+                return Err(anyhow!(
+                    "Check query returned `{}` against the restored database {}.",
+                    !expected,
+                    restored.name
+                ));
+            }
+        }
+        Command::Undelete {
+            table,
+            filter,
+            at,
+            apply,
+        } => {
+            let client = connect(&database).await?;
+            Undeleter {
+                client: &client,
+                tables: table,
+                filter,
+                read_timestamp: at,
+                apply,
+            }
+            .run()
+            .await?;
+        }
+        Command::Revert {
+            table,
+            columns,
+            filter,
+            at,
+            incident_end,
+            conflict,
+            apply,
+        } => {
+            let client = connect(&database).await?;
+            Reverter {
+                client: &client,
+                table,
+                columns,
+                filter,
+                read_timestamp: at,
+                incident_end,
+                conflict_policy: conflict,
+                apply,
+            }
+            .run()
+            .await?;
+        }
+        Command::Export {
+            table,
+            query,
+            at,
+            format,
+            output,
+        } => {
+            let client = connect(&database).await?;
+            let sources = table
+                .iter()
+                .map(|t| ExportSource::table(t))
+                .chain(
+                    query
+                        .iter()
+                        .enumerate()
+                        .map(|(i, q)| ExportSource::query(i, q)),
+                )
+                .collect();
+            Exporter {
+                client: &client,
+                database: database.clone(),
+                sources,
+                read_timestamp: at,
+                format,
+                output,
+            }
+            .run()
+            .await?;
+        }
+        Command::Diff {
+            table,
+            query,
+            key,
+            filter,
+            from,
+            to,
+            format,
+        } => {
+            let client = connect(&database).await?;
+            let diff = Differ {
+                client: &client,
+                table,
+                query,
+                key,
+                filter,
+                from,
+                to,
+            }
+            .run()
+            .await?;
+
+            match format {
+                DiffFormat::Table => println!("{}", diff.to_table()),
+                DiffFormat::Json => println!("{}", diff.to_json_lines()),
+            }
+        }
+        Command::UndoSql {
+            table,
+            filter,
+            at,
+            output,
+        } => {
+            let client = connect(&database).await?;
+            let script = UndoScriptGenerator {
+                client: &client,
+                table,
+                filter,
+                read_timestamp: at,
+            }
+            .run()
+            .await?;
+
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, script + "\n")?;
+                    info!("✅ Undo script written to {}", path.display());
+                }
+                None => println!("{}", script),
+            }
+        }
+        Command::DdlHistory { limit } => {
+            let statements = ddl_history(&admin_client, &instance, &database).await?;
+            info!(
+                "ℹ️ {} schema statements found in the last 7 days",
+                statements.len()
+            );
+            for statement in statements
+                .iter()
+                .skip(statements.len().saturating_sub(limit))
+            {
+                debug!("Operation: {}", statement.operation);
+                println!(
+                    "{}  {}",
+                    statement
+                        .commit_timestamp
+                        .map(|ts| ts.format(&time::format_description::well_known::Rfc3339))
+                        .transpose()?
+                        .unwrap_or_else(|| "(pending)".to_string()),
+                    statement.statement
+                );
+            }
+        }
+        Command::Schema { at, from, to } => {
+            let client = connect(&database).await?;
+            let bound = |ts: Option<OffsetDateTime>| {
+                ts.as_ref()
+                    .map(read_timestamp)
+                    .unwrap_or_else(TimestampBound::strong_read)
+            };
+
+            match from {
+                Some(from) => {
+                    info!(
+                        "❔ Comparing schema at {} and {}...",
+                        from,
+                        to.map(|ts| ts.to_string())
+                            .unwrap_or_else(|| "now".to_string())
+                    );
+                    let before = DatabaseSchema::read(&client, read_timestamp(&from)).await?;
+                    let after = DatabaseSchema::read(&client, bound(to)).await?;
+                    let diff = before.diff(&after);
+                    if diff.is_empty() {
+                        info!("✅ No schema changes found");
+                    } else {
+                        println!("{}", diff);
+                    }
+                }
+                None => {
+                    let schema = DatabaseSchema::read(&client, bound(at)).await?;
+                    println!("{}", schema.to_ddl());
+                }
+            }
+        }
+        Command::Timeline { timeline, limit } => {
+            timeline.errors.check()?;
+            let earliest_time = Retention::fetch(&admin_client, &database)
+                .await?
+                .log()
+                .earliest_version_time;
+            let horizon = Horizon::new(earliest_time, *timeline.horizon_margin);
+            let start = match timeline.start {
+                Some(ts) if ts >= horizon.earliest_safe() => ts,
+                Some(ts) => {
+                    warn!(
+                        "Start {} is within {}ms of garbage collection. Starting from {}.",
+                        ts,
+                        timeline.horizon_margin,
+                        horizon.earliest_safe()
+                    );
+                    horizon.earliest_safe()
+                }
+                None => horizon.earliest_safe(),
+            };
+            let client = connect(&database).await?;
+            let end = match timeline.end {
+                Some(ts) => ts,
+                None => database_time(&client).await?,
+            };
+
+            let changes = TimelineFinder {
+                client: &client,
+                start,
+                end,
+                accuracy: *timeline.accuracy,
+                limit,
+                query: timeline.query,
+                errors: timeline.errors,
+            }
+            .run()
+            .await?;
+
+            for change in changes {
+                println!(
+                    "{} - {}  {} -> {}",
+                    change
+                        .before
+                        .format(&time::format_description::well_known::Rfc3339)?,
+                    change
+                        .after
+                        .format(&time::format_description::well_known::Rfc3339)?,
+                    change.old_value,
+                    change.new_value
+                );
+            }
+        }
+        Command::Culprit {
+            search,
+            change_stream,
+        } => {
+            let result =
+                find_recovery_timestamp(&admin_client, &instance, &database, search).await?;
+            let client = connect(&database).await?;
+
+            info!(
+                "❔ Reading change stream {} between {} and {}...",
+                change_stream, result.last_good, result.first_bad
+            );
+            let records = ChangeStreamReader {
+                client: &client,
+                change_stream,
+                start: result.last_good,
+                end: result.first_bad,
+            }
+            .run()
+            .await?
+            .into_iter()
+            .filter(|record| record.commit_timestamp > result.last_good)
+            .collect::<Vec<_>>();
+
+            info!(
+                "ℹ️ {} data change records found inside the search result",
+                records.len()
+            );
+            for record in records {
+                println!("{}", record.summary()?);
+            }
+        }
+        Command::Invert {
+            change_stream,
+            commit_timestamp,
+            transaction_id,
+            from,
+            to,
+            output,
+            conflict,
+            apply,
+        } => {
+            let client = connect(&database).await?;
+            let (start, end) = match (commit_timestamp, from) {
+                (Some(ts), _) => (ts, ts),
+                (None, Some(from)) => (
+                    from,
+                    match to {
+                        Some(ts) => ts,
+                        None => database_time(&client).await?,
+                    },
+                ),
+                (None, None) => return Err(anyhow!("A commit timestamp or period is required.")),
+            };
+
+            let statements = TransactionInverter {
+                client: &client,
+                change_stream,
+                transaction_id,
+                start,
+                end,
+                conflict_policy: conflict,
+                apply,
+            }
+            .run()
+            .await?;
+
+            let script = statements.join("\n");
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, script + "\n")?;
+                    info!("✅ Inverse DML written to {}", path.display());
+                }
+                None => println!("{}", script),
+            }
+        }
+        Command::Protect {
+            all_databases,
+            record,
+        } => {
+            DropProtector {
+                admin_client: &admin_client,
+                instance: instance.clone(),
+                database: database.clone(),
+                all_databases,
+                record,
+            }
+            .protect()
+            .await?;
+        }
+        Command::Unprotect {
+            all_databases,
+            record,
+        } => {
+            DropProtector {
+                admin_client: &admin_client,
+                instance: instance.clone(),
+                database: database.clone(),
+                all_databases,
+                record,
+            }
+            .unprotect()
+            .await?;
+        }
+        Command::Retention {
+            set,
+            allow_shorten,
+            apply,
+        } => {
+            let statement = RetentionUpdater {
+                admin_client: &admin_client,
+                database: database.clone(),
+                period: set,
+                allow_shorten,
+                apply,
+            }
+            .run()
+            .await?;
+            if let Some(statement) = statement {
+                println!("{};", statement);
+            }
         }
     }
     Ok(())
@@ -404,9 +1887,14 @@ mod tests {
     };
     use log::info;
     use std::{env, time::Duration};
-    use time::OffsetDateTime;
+    use time::{ext::NumericalDuration, macros::datetime, OffsetDateTime};
 
-    use crate::{TimestampFinder, ToOffsetDateTime};
+    use crate::commits::CommitFinder;
+    use crate::status::{ErrorOptions, QueryError};
+    use crate::timeline::TimelineFinder;
+    use crate::{
+        parse_candidates, parse_step, Horizon, Polarity, TimestampFinder, ToOffsetDateTime,
+    };
 
     struct TestSpanner {
         project: String,
@@ -443,6 +1931,112 @@ mod tests {
         }
     }
 
+    /// Test that candidate timestamps are parsed, skipping blank lines and comments.
+    #[test]
+    fn test_parse_candidates() {
+        let candidates =
+            parse_candidates("# Alert fired\n2023-03-01T14:05:00Z\n\n  2023-03-01T14:10:00.5Z  \n")
+                .unwrap();
+        assert_eq!(
+            candidates,
+            vec![
+                datetime!(2023-03-01 14:05 UTC),
+                datetime!(2023-03-01 14:10:00.5 UTC)
+            ]
+        );
+        assert!(parse_candidates("yesterday").is_err());
+    }
+
+    /// Test that a gallop step must be positive.
+    #[test]
+    fn test_parse_step() {
+        assert_eq!(*parse_step("500").unwrap(), 500.milliseconds());
+        assert!(parse_step("0").is_err());
+        assert!(parse_step("-500").is_err());
+        assert!(parse_step("soon").is_err());
+    }
+
+    /// Test that the horizon is extrapolated from when it was fetched, with the margin kept
+    /// ahead of it.
+    #[test]
+    fn test_horizon() {
+        let earliest_version_time = datetime!(2023-03-01 00:00 UTC);
+        let horizon = Horizon {
+            earliest_version_time,
+            fetched: std::time::Instant::now() - Duration::from_secs(30),
+            margin: 60.seconds(),
+        };
+
+        let earliest = horizon.earliest();
+        assert!(earliest >= earliest_version_time + 30.seconds());
+        assert!(earliest < earliest_version_time + 40.seconds());
+        let earliest_safe = horizon.earliest_safe();
+        assert!(earliest_safe >= earliest_version_time + 90.seconds());
+        assert!(earliest_safe < earliest_version_time + 100.seconds());
+    }
+
+    /// Test that the start of an interval is only moved once the horizon overtakes it, and that
+    /// an interval overtaken entirely fails.
+    #[test]
+    fn test_horizon_overtaken() {
+        let earliest_version_time = datetime!(2023-03-01 00:00 UTC);
+        let horizon = Horizon::new(earliest_version_time, 60.seconds());
+        let end = earliest_version_time + 1.hours();
+
+        assert_eq!(
+            horizon
+                .overtaken(&(earliest_version_time + 10.minutes()), &end)
+                .unwrap(),
+            None
+        );
+        let moved = horizon
+            .overtaken(&earliest_version_time, &end)
+            .unwrap()
+            .unwrap();
+        assert!(moved >= earliest_version_time + 60.seconds());
+        assert!(moved < earliest_version_time + 10.minutes());
+        assert!(horizon
+            .overtaken(
+                &earliest_version_time,
+                &(earliest_version_time + 30.seconds())
+            )
+            .is_err());
+    }
+
+    /// Test that probes divide the search interval evenly.
+    #[test]
+    fn test_probe_timestamps() {
+        let start = datetime!(2023-03-01 00:00 UTC);
+
+        assert_eq!(
+            TimestampFinder::probe_timestamps(&start, &(start + 4.seconds()), 1),
+            vec![start + 2.seconds()]
+        );
+        assert_eq!(
+            TimestampFinder::probe_timestamps(&start, &(start + 4.seconds()), 3),
+            vec![
+                start + 1.seconds(),
+                start + 2.seconds(),
+                start + 3.seconds()
+            ]
+        );
+        assert_eq!(
+            TimestampFinder::probe_timestamps(&start, &(start + 2.nanoseconds()), 3),
+            vec![start + 1.nanoseconds()]
+        );
+    }
+
+    /// Test that failures are only treated as `false` when searching from `true` to `false`.
+    #[test]
+    fn test_polarity_false_on() {
+        let errors = ErrorOptions::default();
+        assert_eq!(
+            Polarity::TrueToFalse.false_on(&errors),
+            [QueryError::SchemaMissing]
+        );
+        assert!(Polarity::FalseToTrue.false_on(&errors).is_empty());
+    }
+
     /// Test Spanner connectivity and simple queries.
     #[tokio::test]
     async fn test_connectivity() -> Result<()> {
@@ -575,24 +2169,32 @@ mod tests {
             let target_accuracy = time::Duration::milliseconds(10);
 
             // Try to find the correct insertion timestamp.
-            let finder = TimestampFinder {
+            let mut finder = TimestampFinder {
                 start: insert_timestamp,
                 end: database_time(&client).await?,
                 accuracy: target_accuracy,
                 query: format!("SELECT COUNT(*) > 0 FROM {}", test_table),
+                polarity: Polarity::TrueToFalse,
+                concurrency: 1,
+                gallop: None,
+                hints: vec![],
+                errors: ErrorOptions::default(),
+                horizon: None,
                 client,
             };
 
-            let found_timestamp = finder.run().await?;
+            let result = finder.run().await?;
 
-            if found_timestamp <= target_timestamp
-                && (target_timestamp - found_timestamp) < target_accuracy
+            if result.last_good < target_timestamp
+                && result.first_bad >= target_timestamp
+                && result.uncertainty() < target_accuracy
             {
                 Ok(())
             } else {
                 Err(anyhow!(
-                    "Found timestamp {} and target timestamp {}",
-                    found_timestamp,
+                    "Found bracket {} - {} and target timestamp {}",
+                    result.last_good,
+                    result.first_bad,
                     target_timestamp
                 ))
             }
@@ -633,15 +2235,21 @@ mod tests {
             drop_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
 
             // Try to find the correct insertion timestamp after the table has been dropped.
-            let finder = TimestampFinder {
+            let mut finder = TimestampFinder {
                 start: start_timestamp,
                 end: database_time(&client).await?,
                 accuracy: target_accuracy,
                 query: format!("SELECT COUNT(*) > 0 FROM {}", test_table),
+                polarity: Polarity::TrueToFalse,
+                concurrency: 1,
+                gallop: None,
+                hints: vec![],
+                errors: ErrorOptions::default(),
+                horizon: None,
                 client,
             };
 
-            let found_timestamp = finder.run().await?;
+            let found_timestamp = finder.run().await?.last_good;
 
             if found_timestamp <= target_timestamp
                 && (target_timestamp - found_timestamp) <= target_accuracy
@@ -661,4 +2269,173 @@ mod tests {
         }
         .await
     }
+
+    /// Test finding the last timestamp before bad data appeared
+    #[tokio::test]
+    async fn test_dml_appearance_recovery() -> Result<()> {
+        let spanner = TestSpanner::new()?;
+        let (client, admin_client) = spanner.connect().await?;
+        let test_table = format!("table_{}", uuid::Uuid::new_v4().simple());
+
+        let operation = async {
+            create_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
+            let start_timestamp = database_time(&client).await?;
+
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+
+            let (target_timestamp, _) = insert_test_record(&client, &test_table).await?;
+
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+
+            let target_accuracy = time::Duration::milliseconds(10);
+
+            // Try to find the last timestamp before the row was inserted.
+            let mut finder = TimestampFinder {
+                start: start_timestamp,
+                end: database_time(&client).await?,
+                accuracy: target_accuracy,
+                query: format!("SELECT COUNT(*) > 0 FROM {}", test_table),
+                polarity: Polarity::FalseToTrue,
+                concurrency: 1,
+                gallop: None,
+                hints: vec![],
+                errors: ErrorOptions::default(),
+                horizon: None,
+                client,
+            };
+
+            let found_timestamp = finder.run().await?.last_good;
+
+            if found_timestamp < target_timestamp
+                && (target_timestamp - found_timestamp) < target_accuracy
+            {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "Found timestamp {} and target timestamp {}",
+                    found_timestamp,
+                    target_timestamp
+                ))
+            }
+        }
+        .await;
+
+        // Always drop the test table afterwards.
+        drop_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
+
+        operation
+    }
+
+    /// Test snapping the search result to the exact commit which inserted bad data
+    #[tokio::test]
+    async fn test_commit_snapping() -> Result<()> {
+        let spanner = TestSpanner::new()?;
+        let (client, admin_client) = spanner.connect().await?;
+        let test_table = format!("table_{}", uuid::Uuid::new_v4().simple());
+
+        let operation = async {
+            create_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
+            let start_timestamp = database_time(&client).await?;
+
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+
+            let (target_timestamp, _) = insert_test_record(&client, &test_table).await?;
+
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+
+            let mut finder = TimestampFinder {
+                start: start_timestamp,
+                end: database_time(&client).await?,
+                accuracy: time::Duration::milliseconds(100),
+                query: format!("SELECT COUNT(*) > 0 FROM {}", test_table),
+                polarity: Polarity::FalseToTrue,
+                concurrency: 1,
+                gallop: None,
+                hints: vec![],
+                errors: ErrorOptions::default(),
+                horizon: None,
+                client,
+            };
+
+            let result = finder.run().await?;
+            let commits = CommitFinder {
+                client: &finder.client,
+                tables: vec![test_table.clone()],
+                change_stream: None,
+            }
+            .run(&result.last_good, &result.first_bad)
+            .await?;
+            let result = finder.snap_to_commit(result, &commits).await?;
+
+            if result.first_bad == target_timestamp
+                && result.last_good == target_timestamp - time::Duration::nanoseconds(1)
+            {
+                Ok(())
+            } else {
+                Err(anyhow!(
+                    "Found bracket {} - {} and target timestamp {}",
+                    result.last_good,
+                    result.first_bad,
+                    target_timestamp
+                ))
+            }
+        }
+        .await;
+
+        // Always drop the test table afterwards.
+        drop_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
+
+        operation
+    }
+
+    /// Test that every change of a query result is listed
+    #[tokio::test]
+    async fn test_timeline() -> Result<()> {
+        let spanner = TestSpanner::new()?;
+        let (client, admin_client) = spanner.connect().await?;
+        let test_table = format!("table_{}", uuid::Uuid::new_v4().simple());
+
+        let operation = async {
+            create_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
+
+            let start = database_time(&client).await?;
+            let (first_timestamp, _) = insert_test_record(&client, &test_table).await?;
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+            let (second_timestamp, _) = insert_test_record(&client, &test_table).await?;
+            tokio::time::sleep(Duration::from_millis(1000)).await;
+
+            let changes = TimelineFinder {
+                client: &client,
+                start,
+                end: database_time(&client).await?,
+                accuracy: time::Duration::milliseconds(10),
+                limit: 10,
+                query: format!("SELECT COUNT(*) FROM {}", test_table),
+                errors: ErrorOptions::default(),
+            }
+            .run()
+            .await?;
+
+            let found = changes
+                .iter()
+                .map(|c| (c.old_value.as_str(), c.new_value.as_str()))
+                .collect::<Vec<_>>();
+            if found == vec![("(0)", "(1)"), ("(1)", "(2)")]
+                && changes[0].before < first_timestamp
+                && changes[0].after >= first_timestamp
+                && changes[1].before < second_timestamp
+                && changes[1].after >= second_timestamp
+            {
+                Ok(())
+            } else {
+                Err(anyhow!("Found changes {:?}", changes))
+            }
+        }
+        .await;
+
+        // Always drop the test table afterwards.
+        drop_test_table(&admin_client, &spanner.database_path(), &test_table).await?;
+
+        operation
+    }
 }
@@ -0,0 +1,98 @@
+use anyhow::Result;
+use google_cloud_googleapis::longrunning::Operation;
+use google_cloud_googleapis::spanner::admin::database::v1::{
+    ListBackupOperationsRequest, ListDatabaseOperationsRequest,
+};
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use prost::Message;
+
+/// Build an operations filter matching the metadata type and an additional condition.
+fn operation_filter(metadata_type: &str, filter: &str) -> String {
+    format!(
+        "(metadata.@type=type.googleapis.com/google.spanner.admin.database.v1.{}) AND ({})",
+        metadata_type, filter
+    )
+}
+
+/// Decode the metadata of a long-running operation, returning it with the completion state.
+fn decode_metadata<M: Message + Default>(operation: Operation) -> Option<(bool, M)> {
+    let done = operation.done;
+    operation
+        .metadata
+        .and_then(|metadata| M::decode(metadata.value.as_slice()).ok())
+        .map(|metadata| (done, metadata))
+}
+
+/// Return the most recent backup operation in an instance with the given metadata type
+/// that refers to the named backup.
+pub async fn backup_operation<M: Message + Default>(
+    admin_client: &AdminClient,
+    instance: &str,
+    metadata_type: &str,
+    name: &str,
+) -> Result<Option<(bool, M)>> {
+    let operations = admin_client
+        .database()
+        .list_backup_operations(
+            ListBackupOperationsRequest {
+                parent: instance.to_string(),
+                filter: operation_filter(metadata_type, &format!("metadata.name={}", name)),
+                page_size: 1,
+                page_token: "".to_string(),
+            },
+            None,
+        )
+        .await?;
+
+    Ok(operations.into_iter().find_map(decode_metadata))
+}
+
+/// Return every database operation in an instance with the given metadata type which
+/// matches the filter, with its name and completion state.
+pub async fn list_database_operations<M: Message + Default>(
+    admin_client: &AdminClient,
+    instance: &str,
+    metadata_type: &str,
+    filter: &str,
+) -> Result<Vec<(String, bool, M)>> {
+    let operations = admin_client
+        .database()
+        .list_database_operations(
+            ListDatabaseOperationsRequest {
+                parent: instance.to_string(),
+                filter: operation_filter(metadata_type, filter),
+                page_size: 0,
+                page_token: "".to_string(),
+            },
+            None,
+        )
+        .await?;
+
+    Ok(operations
+        .into_iter()
+        .filter_map(|operation| {
+            let name = operation.name.clone();
+            decode_metadata(operation).map(|(done, metadata)| (name, done, metadata))
+        })
+        .collect())
+}
+
+/// Return the most recent database operation in an instance with the given metadata type
+/// that refers to the named database.
+pub async fn database_operation<M: Message + Default>(
+    admin_client: &AdminClient,
+    instance: &str,
+    metadata_type: &str,
+    name: &str,
+) -> Result<Option<(bool, M)>> {
+    Ok(list_database_operations(
+        admin_client,
+        instance,
+        metadata_type,
+        &format!("metadata.name={}", name),
+    )
+    .await?
+    .into_iter()
+    .next()
+    .map(|(_, done, metadata)| (done, metadata)))
+}
@@ -0,0 +1,218 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use google_cloud_googleapis::spanner::admin::database::v1::{
+    Database, ListDatabasesRequest, UpdateDatabaseRequest,
+};
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use log::{info, warn};
+use prost_types::FieldMask;
+
+use crate::retention::get_database;
+
+/// Default file recording the drop protection of databases before it was enabled.
+pub const DEFAULT_RECORD: &str = "drop-protection.json";
+
+/// Record the drop protection of a database before it is enabled, keeping the setting
+/// recorded first if the database was protected before.
+fn record_previous(record: &mut BTreeMap<String, bool>, database: &str, enabled: bool) {
+    record.entry(database.to_string()).or_insert(enabled);
+}
+
+/// Return whether a database is the given database, or is in the given instance if every
+/// database in it is covered.
+fn covers(name: &str, instance: &str, database: &str, all_databases: bool) -> bool {
+    if all_databases {
+        name.strip_prefix(instance)
+            .is_some_and(|rest| rest.starts_with("/databases/"))
+    } else {
+        name == database
+    }
+}
+
+/// Logic to enable drop protection on a database, or on every database in its instance,
+/// and to put the previous settings back afterwards.
+pub struct DropProtector<'a> {
+    pub admin_client: &'a AdminClient,
+    pub instance: String,
+    pub database: String,
+    pub all_databases: bool,
+    pub record: PathBuf,
+}
+
+impl<'a> DropProtector<'a> {
+    /// Return the databases to protect.
+    async fn databases(&self) -> Result<Vec<Database>> {
+        if !self.all_databases {
+            return Ok(vec![get_database(self.admin_client, &self.database).await?]);
+        }
+        Ok(self
+            .admin_client
+            .database()
+            .list_databases(
+                ListDatabasesRequest {
+                    parent: self.instance.clone(),
+                    page_size: 0,
+                    page_token: "".to_string(),
+                },
+                None,
+            )
+            .await?)
+    }
+
+    /// Set the drop protection of a database, waiting for the update to complete.
+    async fn set(&self, database: &str, enabled: bool) -> Result<()> {
+        let mut operation = self
+            .admin_client
+            .database()
+            .update_database(
+                UpdateDatabaseRequest {
+                    database: Some(Database {
+                        name: database.to_string(),
+                        enable_drop_protection: enabled,
+                        ..Default::default()
+                    }),
+                    update_mask: Some(FieldMask {
+                        paths: vec!["enable_drop_protection".to_string()],
+                    }),
+                },
+                None,
+            )
+            .await?;
+        operation.wait(None).await?;
+        Ok(())
+    }
+
+    /// Read the recorded previous settings, which are empty if nothing has been recorded.
+    fn read_record(&self) -> Result<BTreeMap<String, bool>> {
+        if !self.record.exists() {
+            return Ok(BTreeMap::new());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(
+            &self.record,
+        )?)?)
+    }
+
+    /// Enable drop protection, recording the previous settings.
+    pub async fn protect(&self) -> Result<()> {
+        if self.all_databases {
+            warn!("Instances have no drop protection of their own, but an instance cannot be deleted while any of its databases are protected.");
+        }
+
+        let mut record = self.read_record()?;
+        for database in self.databases().await? {
+            record_previous(&mut record, &database.name, database.enable_drop_protection);
+            // Write the record before each change, so that it is never lost.
+            std::fs::write(&self.record, serde_json::to_string_pretty(&record)?)?;
+            if database.enable_drop_protection {
+                info!(
+                    "ℹ️ {} is already protected from being dropped",
+                    database.name
+                );
+                continue;
+            }
+            info!("❔ Enabling drop protection on {}...", database.name);
+            self.set(&database.name, true).await?;
+            info!("✅ {} is protected from being dropped", database.name);
+        }
+        info!(
+            "ℹ️ Previous settings recorded in {}, use unprotect to restore them",
+            self.record.display()
+        );
+        Ok(())
+    }
+
+    /// Restore the drop protection recorded before it was enabled, for the database or every
+    /// database in its instance, removing them from the record.
+    pub async fn unprotect(&self) -> Result<()> {
+        let mut record = self.read_record()?;
+        let restores = record
+            .iter()
+            .filter(|(database, _)| {
+                covers(database, &self.instance, &self.database, self.all_databases)
+            })
+            .map(|(database, enabled)| (database.clone(), *enabled))
+            .collect::<Vec<_>>();
+        if restores.is_empty() {
+            return Err(anyhow!(
+                "No previous drop protection settings of {} are recorded in {}.",
+                if self.all_databases {
+                    &self.instance
+                } else {
+                    &self.database
+                },
+                self.record.display()
+            ));
+        }
+
+        for (database, enabled) in restores {
+            record.remove(&database);
+            if enabled {
+                info!(
+                    "ℹ️ {} was already protected, leaving it protected",
+                    database
+                );
+                continue;
+            }
+            info!("❔ Disabling drop protection on {}...", database);
+            self.set(&database, false).await?;
+            info!("✅ {} can be dropped again", database);
+        }
+
+        // Settings of other databases stay recorded until they are restored.
+        if record.is_empty() {
+            std::fs::remove_file(&self.record)?;
+        } else {
+            std::fs::write(&self.record, serde_json::to_string_pretty(&record)?)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::{covers, record_previous};
+
+    /// Test that protecting a database twice keeps the setting from before the first time.
+    #[test]
+    fn test_record_previous() {
+        let mut record = BTreeMap::new();
+        record_previous(&mut record, "a", false);
+        record_previous(&mut record, "b", true);
+        record_previous(&mut record, "a", true);
+        assert_eq!(
+            record,
+            BTreeMap::from([("a".to_string(), false), ("b".to_string(), true)])
+        );
+    }
+
+    /// Test that restoring covers the given database, or every database in its instance.
+    #[test]
+    fn test_covers() {
+        let instance = "projects/p/instances/i";
+        let database = "projects/p/instances/i/databases/d";
+
+        assert!(covers(database, instance, database, false));
+        assert!(!covers(
+            "projects/p/instances/i/databases/e",
+            instance,
+            database,
+            false
+        ));
+        assert!(covers(
+            "projects/p/instances/i/databases/e",
+            instance,
+            database,
+            true
+        ));
+        assert!(!covers(
+            "projects/p/instances/i2/databases/d",
+            instance,
+            database,
+            true
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use anyhow::{anyhow, Result};
+use google_cloud_googleapis::spanner::admin::database::v1::{
+    restore_database_request::Source, Database, OptimizeRestoredDatabaseMetadata,
+    RestoreDatabaseMetadata, RestoreDatabaseRequest,
+};
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use indicatif::ProgressBar;
+use log::{debug, info, warn};
+
+use crate::backup::POLL_INTERVAL;
+use crate::operations::database_operation;
+
+/// How long to wait for a restored database to be optimized.
+const OPTIMIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2 * 60 * 60);
+
+/// Number of polls in a row which may fail to read the optimize operation before giving up.
+const MAX_UNKNOWN_POLLS: u32 = 12;
+
+/// Logic to restore a backup into a new database, waiting for it to be optimized.
+pub struct DatabaseRestorer<'a> {
+    pub admin_client: &'a AdminClient,
+    pub instance: String,
+    pub database_id: String,
+    pub backup: String,
+}
+
+impl<'a> DatabaseRestorer<'a> {
+    /// Return the full path of the restored database.
+    fn database_path(&self) -> String {
+        format!("{}/databases/{}", self.instance, self.database_id)
+    }
+
+    /// Return the progress of the restore operation, along with the name of the
+    /// operation optimizing the restored database once it is known.
+    async fn restore_progress(&self) -> Result<Option<(i32, String)>> {
+        let operation = database_operation::<RestoreDatabaseMetadata>(
+            self.admin_client,
+            &self.instance,
+            "RestoreDatabaseMetadata",
+            &self.database_path(),
+        )
+        .await?;
+
+        Ok(operation.map(|(_, metadata)| {
+            (
+                metadata
+                    .progress
+                    .map(|progress| progress.progress_percent)
+                    .unwrap_or_default(),
+                metadata.optimize_database_operation_name,
+            )
+        }))
+    }
+
+    /// Return the progress of the optimize operation, along with whether it has completed.
+    async fn optimize_progress(&self) -> Result<Option<(bool, i32)>> {
+        let operation = database_operation::<OptimizeRestoredDatabaseMetadata>(
+            self.admin_client,
+            &self.instance,
+            "OptimizeRestoredDatabaseMetadata",
+            &self.database_path(),
+        )
+        .await?;
+
+        Ok(operation.map(|(done, metadata)| {
+            (
+                done,
+                metadata
+                    .progress
+                    .map(|progress| progress.progress_percent)
+                    .unwrap_or_default(),
+            )
+        }))
+    }
+
+    /// Wait for the restored database to be optimized. The database is readable while it
+    /// is being optimized, but performance is reduced until this completes.
+    async fn wait_for_optimization(&self) -> Result<()> {
+        info!(
+            "⚙️ Optimizing restored database {}...",
+            self.database_path()
+        );
+
+        let deadline = std::time::Instant::now() + OPTIMIZE_TIMEOUT;
+        let bar = ProgressBar::new(100);
+        let mut unknown_polls = 0;
+        loop {
+            match self.optimize_progress().await {
+                Ok(Some((true, _))) => break,
+                Ok(Some((false, percent))) => {
+                    bar.set_position(percent as u64);
+                    unknown_polls = 0;
+                }
+                Ok(None) => unknown_polls += 1,
+                Err(e) => {
+                    debug!("Unable to read optimization progress: {}", e);
+                    unknown_polls += 1;
+                }
+            }
+
+            if unknown_polls >= MAX_UNKNOWN_POLLS {
+                bar.abandon();
+                return Err(anyhow!(
+                    "Unable to find the optimize operation of {}.",
+                    self.database_path()
+                ));
+            }
+            if std::time::Instant::now() >= deadline {
+                bar.abandon();
+                return Err(anyhow!(
+                    "{} was still being optimized after {} minutes.",
+                    self.database_path(),
+                    OPTIMIZE_TIMEOUT.as_secs() / 60
+                ));
+            }
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+        bar.finish();
+
+        Ok(())
+    }
+
+    /// Execute the restore, polling the restore and optimize operations until they complete.
+    pub async fn run(&self) -> Result<Database> {
+        info!(
+            "♻️ Restoring backup {} into database {}...",
+            self.backup,
+            self.database_path()
+        );
+
+        let mut operation = self
+            .admin_client
+            .database()
+            .restore_database(
+                RestoreDatabaseRequest {
+                    parent: self.instance.clone(),
+                    database_id: self.database_id.clone(),
+                    encryption_config: None,
+                    source: Some(Source::Backup(self.backup.clone())),
+                },
+                None,
+            )
+            .await?;
+
+        let bar = ProgressBar::new(100);
+        let mut optimize_operation = String::new();
+        let database = loop {
+            if let Some(database) = operation.poll().await? {
+                break database;
+            }
+
+            match self.restore_progress().await {
+                Ok(Some((percent, optimize))) => {
+                    bar.set_position(percent as u64);
+                    optimize_operation = optimize;
+                }
+                Ok(None) => {}
+                Err(e) => debug!("Unable to read restore progress: {}", e),
+            }
+
+            tokio::time::sleep(POLL_INTERVAL).await;
+        };
+        bar.finish();
+
+        info!("✅ Restored database {}", database.name);
+
+        if optimize_operation.is_empty() {
+            // The optimize operation name is only reported once the restore has progressed,
+            // so check whether one was started after the final poll.
+            if let Ok(Some((_, optimize))) = self.restore_progress().await {
+                optimize_operation = optimize;
+            }
+        }
+
+        if !optimize_operation.is_empty() {
+            debug!("Optimize operation: {}", optimize_operation);
+            // The restored database is readable before it is optimized, so stop waiting
+            // rather than failing the restore.
+            match self.wait_for_optimization().await {
+                Ok(()) => info!("✅ Optimized database {}", database.name),
+                Err(e) => warn!("{} Stopped waiting for optimization.", e),
+            }
+        }
+
+        Ok(database)
+    }
+}
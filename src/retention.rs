@@ -0,0 +1,281 @@
+use anyhow::{anyhow, Result};
+use google_cloud_googleapis::spanner::admin::database::v1::{
+    Database, DatabaseDialect, GetDatabaseRequest, UpdateDatabaseDdlRequest,
+};
+use google_cloud_spanner::admin::client::Client as AdminClient;
+use indicatif::ProgressBar;
+use log::{info, warn};
+use time::{ext::NumericalDuration, OffsetDateTime};
+
+use crate::schema::quote_identifier;
+use crate::ToOffsetDateTime;
+
+/// The longest version retention period Spanner allows.
+pub const MAX_RETENTION_PERIOD: time::Duration = time::Duration::days(7);
+
+/// The shortest version retention period Spanner allows.
+pub const MIN_RETENTION_PERIOD: time::Duration = time::Duration::hours(1);
+
+/// Parse a version retention period as reported by Spanner, such as `1h`, `90m` or `7d`.
+pub fn parse_retention_period(period: &str) -> Result<time::Duration> {
+    let invalid = || anyhow!("Invalid version retention period {}.", period);
+    let unit = period.chars().last().ok_or_else(invalid)?;
+    let value = period[..period.len() - unit.len_utf8()]
+        .parse::<i64>()
+        .map_err(|_| invalid())?;
+    match unit {
+        's' => Ok(value.seconds()),
+        'm' => Ok(value.minutes()),
+        'h' => Ok(value.hours()),
+        'd' => Ok(value.days()),
+        _ => Err(invalid()),
+    }
+}
+
+/// Format a version retention period in the largest unit which represents it exactly.
+pub fn format_retention_period(period: time::Duration) -> String {
+    let seconds = period.whole_seconds();
+    if seconds % 86400 == 0 {
+        format!("{}d", seconds / 86400)
+    } else if seconds % 3600 == 0 {
+        format!("{}h", seconds / 3600)
+    } else if seconds % 60 == 0 {
+        format!("{}m", seconds / 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Return the DDL statement setting the version retention period of a database.
+pub fn alter_retention_ddl(
+    database_id: &str,
+    dialect: DatabaseDialect,
+    period: time::Duration,
+) -> String {
+    match dialect {
+        DatabaseDialect::Postgresql => format!(
+            "ALTER DATABASE \"{}\" SET spanner.version_retention_period = '{}'",
+            database_id,
+            format_retention_period(period)
+        ),
+        _ => format!(
+            "ALTER DATABASE {} SET OPTIONS (version_retention_period = '{}')",
+            quote_identifier(database_id),
+            format_retention_period(period)
+        ),
+    }
+}
+
+/// Format a duration to the second, such as `1h 02m 03s`.
+pub fn format_duration(duration: time::Duration) -> String {
+    let seconds = duration.whole_seconds().max(0);
+    match (seconds / 3600, seconds / 60 % 60, seconds % 60) {
+        (0, 0, s) => format!("{}s", s),
+        (0, m, s) => format!("{}m {:02}s", m, s),
+        (h, m, s) => format!("{}h {:02}m {:02}s", h, m, s),
+    }
+}
+
+/// The version retention settings of a database.
+#[derive(Debug, Clone)]
+pub struct Retention {
+    pub period: time::Duration,
+    pub earliest_version_time: OffsetDateTime,
+}
+
+impl Retention {
+    /// Read the version retention settings of a database.
+    pub async fn fetch(admin_client: &AdminClient, database: &str) -> Result<Self> {
+        Self::from_database(&get_database(admin_client, database).await?)
+    }
+
+    /// Return the version retention settings of a database which has been read.
+    pub fn from_database(database: &Database) -> Result<Self> {
+        Ok(Retention {
+            period: parse_retention_period(&database.version_retention_period)?,
+            earliest_version_time: database
+                .earliest_version_time
+                .as_ref()
+                .ok_or_else(|| anyhow!("Database {} has no earliest version time.", database.name))?
+                .to_offset_date_time(),
+        })
+    }
+
+    /// Log the earliest recovery time and the retention period.
+    pub fn log(self) -> Self {
+        info!("⏱️ Earliest recovery time: {}", self.earliest_version_time);
+        info!(
+            "⏱️ Retention period: {}",
+            format_retention_period(self.period)
+        );
+        self
+    }
+
+    /// Return when a version will be garbage collected, or `None` if it already has been.
+    pub fn expiry(&self, version_time: &OffsetDateTime) -> Option<OffsetDateTime> {
+        if *version_time < self.earliest_version_time {
+            None
+        } else {
+            Some(*version_time + self.period)
+        }
+    }
+}
+
+/// Read a database.
+pub async fn get_database(admin_client: &AdminClient, database: &str) -> Result<Database> {
+    Ok(admin_client
+        .database()
+        .get_database(
+            GetDatabaseRequest {
+                name: database.to_string(),
+            },
+            None,
+        )
+        .await?
+        .into_inner())
+}
+
+/// Logic to show the version retention period of a database, and to change it, previewing
+/// the DDL statement unless the change is applied.
+pub struct RetentionUpdater<'a> {
+    pub admin_client: &'a AdminClient,
+    pub database: String,
+    pub period: Option<time::Duration>,
+    pub allow_shorten: bool,
+    pub apply: bool,
+}
+
+impl<'a> RetentionUpdater<'a> {
+    /// Execute the update, returning the DDL statement if a new period was given.
+    pub async fn run(&self) -> Result<Option<String>> {
+        let database = get_database(self.admin_client, &self.database).await?;
+        let retention = Retention::from_database(&database)?.log();
+
+        let Some(period) = self.period else {
+            return Ok(None);
+        };
+        if period < MIN_RETENTION_PERIOD || period > MAX_RETENTION_PERIOD {
+            return Err(anyhow!(
+                "The retention period must be between {} and {}.",
+                format_retention_period(MIN_RETENTION_PERIOD),
+                format_retention_period(MAX_RETENTION_PERIOD)
+            ));
+        }
+        if period < retention.period {
+            if !self.allow_shorten {
+                return Err(anyhow!(
+                    "{} is shorter than the current retention period of {}, which garbage collects \
+                     older versions straight away. Pass --allow-shorten to shorten it.",
+                    format_retention_period(period),
+                    format_retention_period(retention.period)
+                ));
+            }
+            warn!("Shortening the retention period garbage collects older versions straight away.");
+        }
+
+        let database_id = self.database.rsplit('/').next().unwrap_or_default();
+        let statement = alter_retention_ddl(
+            database_id,
+            DatabaseDialect::from_i32(database.database_dialect)
+                .unwrap_or(DatabaseDialect::GoogleStandardSql),
+            period,
+        );
+        info!("ℹ️ Every version of the data is kept for the whole retention period, so storage use grows with the rate of change of the database, and so does its cost.");
+        info!("ℹ️ Versions already garbage collected are not brought back, so the window only grows as time passes.");
+
+        if self.apply {
+            let mut operation = self
+                .admin_client
+                .database()
+                .update_database_ddl(
+                    UpdateDatabaseDdlRequest {
+                        database: self.database.clone(),
+                        statements: vec![statement.clone()],
+                        operation_id: "".to_string(),
+                    },
+                    None,
+                )
+                .await?;
+            operation.wait(None).await?;
+            info!(
+                "✅ Retention period set to {}",
+                format_retention_period(period)
+            );
+        } else {
+            info!("ℹ️ Retention period not changed (dry run, use --apply to apply)");
+        }
+        Ok(Some(statement))
+    }
+}
+
+/// Show a live countdown until a version is garbage collected.
+pub async fn countdown(version_time: &OffsetDateTime, expiry: &OffsetDateTime) {
+    let bar = ProgressBar::new_spinner();
+    loop {
+        let remaining = *expiry - OffsetDateTime::now_utc();
+        if remaining <= time::Duration::ZERO {
+            break;
+        }
+        bar.set_message(format!(
+            "⏱️ {} remains readable for {}",
+            version_time,
+            format_duration(remaining)
+        ));
+        bar.tick();
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+    bar.finish_with_message(format!("⏱️ {} has been garbage collected", version_time));
+}
+
+#[cfg(test)]
+mod tests {
+    use time::ext::NumericalDuration;
+
+    use google_cloud_googleapis::spanner::admin::database::v1::DatabaseDialect;
+
+    use super::{
+        alter_retention_ddl, format_duration, format_retention_period, parse_retention_period,
+    };
+
+    /// Test that retention periods are parsed in each unit Spanner reports.
+    #[test]
+    fn test_parse_retention_period() {
+        assert_eq!(parse_retention_period("1h").unwrap(), 1.hours());
+        assert_eq!(parse_retention_period("90m").unwrap(), 90.minutes());
+        assert_eq!(parse_retention_period("3600s").unwrap(), 1.hours());
+        assert_eq!(parse_retention_period("7d").unwrap(), 7.days());
+        assert!(parse_retention_period("").is_err());
+        assert!(parse_retention_period("1w").is_err());
+    }
+
+    /// Test that retention periods are formatted in the largest exact unit.
+    #[test]
+    fn test_format_retention_period() {
+        assert_eq!(format_retention_period(7.days()), "7d");
+        assert_eq!(format_retention_period(36.hours()), "36h");
+        assert_eq!(format_retention_period(90.minutes()), "90m");
+        assert_eq!(format_retention_period(3601.seconds()), "3601s");
+    }
+
+    /// Test that the DDL statement is written for the dialect of the database.
+    #[test]
+    fn test_alter_retention_ddl() {
+        assert_eq!(
+            alter_retention_ddl("test-db", DatabaseDialect::GoogleStandardSql, 7.days()),
+            "ALTER DATABASE `test-db` SET OPTIONS (version_retention_period = '7d')"
+        );
+        assert_eq!(
+            alter_retention_ddl("test-db", DatabaseDialect::Postgresql, 7.days()),
+            "ALTER DATABASE \"test-db\" SET spanner.version_retention_period = '7d'"
+        );
+    }
+
+    /// Test that durations are formatted to the second.
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(42.seconds()), "42s");
+        assert_eq!(format_duration(125.seconds()), "2m 05s");
+        assert_eq!(format_duration(3723.seconds()), "1h 02m 03s");
+        assert_eq!(format_duration((-5).seconds()), "0s");
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use google_cloud_googleapis::spanner::v1::Mutation;
+use google_cloud_spanner::client::Client;
+use google_cloud_spanner::mutation::update;
+use google_cloud_spanner::statement::ToKind;
+use google_cloud_spanner::value::TimestampBound;
+use log::{info, warn};
+use time::OffsetDateTime;
+
+use crate::read_timestamp;
+use crate::schema::{quote_identifier, read_keys, read_keys_in, read_rows, TableSchema};
+use crate::undelete::MAX_MUTATIONS_PER_COMMIT;
+use crate::value::{format_row, SpannerValue};
+
+/// How to treat rows which have been changed again since the incident.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ConflictPolicy {
+    /// Leave conflicting rows unchanged and revert the remaining rows
+    Skip,
+    /// Revert conflicting rows, discarding the later changes
+    Overwrite,
+    /// Report conflicting rows and make no changes if there are any
+    Report,
+}
+
+/// A row which must still hold the values it was checked with when a write is made, or must
+/// still not exist if it had none.
+#[derive(Debug, Clone)]
+pub struct ExpectedRow {
+    pub table: String,
+    /// Columns compared, starting with the primary key
+    pub columns: Vec<String>,
+    pub key: Vec<SpannerValue>,
+    pub values: Option<Vec<SpannerValue>>,
+}
+
+/// Apply mutations in one read-write transaction, after reading the expected rows again within
+/// it, so that no other write can land between the check and the commit. Nothing is written
+/// if any of the rows has changed.
+pub async fn apply_if_unchanged(
+    client: &Client,
+    expected: &[ExpectedRow],
+    mutations: Vec<Mutation>,
+) -> Result<()> {
+    let mut groups: BTreeMap<(&str, &[String]), Vec<&ExpectedRow>> = BTreeMap::new();
+    for row in expected {
+        groups
+            .entry((row.table.as_str(), row.columns.as_slice()))
+            .or_default()
+            .push(row);
+    }
+
+    let mut tx = client.begin_read_write_transaction().await?;
+    let result = async {
+        let mut changed_rows = 0;
+        for ((table, columns), rows) in &groups {
+            let key_len = rows[0].key.len();
+            let keys = rows
+                .iter()
+                .map(|row| row.key.as_slice())
+                .collect::<Vec<_>>();
+            let current = read_keys_in(&mut tx, table, columns, &keys)
+                .await?
+                .into_iter()
+                .map(|values| (format_row(&values[..key_len]), values))
+                .collect::<HashMap<_, _>>();
+            changed_rows += rows
+                .iter()
+                .filter(|row| current.get(&format_row(&row.key)) != row.values.as_ref())
+                .count();
+        }
+        if changed_rows == 0 {
+            tx.buffer_write(mutations);
+        }
+        Ok::<_, google_cloud_spanner::client::Error>(changed_rows)
+    }
+    .await;
+
+    let (_, changed_rows) = tx.end(result, None).await?;
+    if changed_rows > 0 {
+        return Err(anyhow!(
+            "{} rows changed while the changes were being made. No changes have been made.",
+            changed_rows
+        ));
+    }
+    Ok(())
+}
+
+/// Logic to write the historical values of selected columns back over the current values.
+pub struct Reverter<'a> {
+    pub client: &'a Client,
+    pub table: String,
+    pub columns: Vec<String>,
+    pub filter: String,
+    pub read_timestamp: OffsetDateTime,
+    pub incident_end: Option<OffsetDateTime>,
+    pub conflict_policy: ConflictPolicy,
+    pub apply: bool,
+}
+
+impl<'a> Reverter<'a> {
+    /// Read the selected columns of the given rows, keyed by primary key.
+    async fn read_by_key(
+        &self,
+        bound: TimestampBound,
+        columns: &[String],
+        keys: &[&[SpannerValue]],
+        key_len: usize,
+    ) -> Result<HashMap<String, Vec<SpannerValue>>> {
+        Ok(read_keys(self.client, bound, &self.table, columns, keys)
+            .await?
+            .into_iter()
+            .map(|row| (format_row(&row[..key_len]), row))
+            .collect())
+    }
+
+    /// Execute the revert.
+    pub async fn run(&self) -> Result<()> {
+        // Without the end of the incident, rows changed again since cannot be told apart from
+        // rows changed by the incident, so they would all be overwritten.
+        if self.incident_end.is_none() && self.conflict_policy != ConflictPolicy::Overwrite {
+            return Err(anyhow!(
+                "--incident-end is required to detect later changes, unless --conflict overwrite \
+                 is chosen."
+            ));
+        }
+
+        let bound = read_timestamp(&self.read_timestamp);
+        let schema = TableSchema::read(self.client, bound.clone(), &self.table).await?;
+        let writable = schema.writable_columns();
+        for column in &self.columns {
+            if schema.primary_key.contains(column) {
+                return Err(anyhow!("Cannot revert primary key column {}.", column));
+            }
+            if !writable.contains(column) {
+                return Err(anyhow!(
+                    "Column {} is not a writable column of {}.",
+                    column,
+                    self.table
+                ));
+            }
+        }
+
+        let key_len = schema.primary_key.len();
+        let mut columns = schema.primary_key.clone();
+        columns.extend(self.columns.iter().cloned());
+
+        info!(
+            "❔ Reading rows of {} matching `{}` at {}...",
+            self.table, self.filter, self.read_timestamp
+        );
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {}",
+            columns
+                .iter()
+                .map(|c| quote_identifier(c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            quote_identifier(&self.table),
+            self.filter
+        );
+        let historical = read_rows(self.client, bound, &sql, columns.len()).await?;
+        let keys = historical
+            .iter()
+            .map(|row| &row[..key_len])
+            .collect::<Vec<_>>();
+
+        let current = self
+            .read_by_key(TimestampBound::strong_read(), &columns, &keys, key_len)
+            .await?;
+        let after_incident = match &self.incident_end {
+            Some(ts) => Some(
+                self.read_by_key(read_timestamp(ts), &columns, &keys, key_len)
+                    .await?,
+            ),
+            None => {
+                warn!("No incident end time given, so every later change will be overwritten.");
+                None
+            }
+        };
+
+        let mut modified_rows = 0;
+        let mut conflicting_rows = 0;
+        let mut reverts = vec![];
+        for row in &historical {
+            let key = format_row(&row[..key_len]);
+            let Some(current_row) = current.get(&key) else {
+                // Deleted rows are restored using `undelete`.
+                continue;
+            };
+            if current_row == row {
+                continue;
+            }
+            modified_rows += 1;
+
+            // A row conflicts if its values changed between the end of the incident and now.
+            let conflict = after_incident
+                .as_ref()
+                .is_some_and(|after| after.get(&key) != Some(current_row));
+
+            if conflict {
+                conflicting_rows += 1;
+                warn!(
+                    "  {}: row {} changed since the incident, now {}",
+                    self.table,
+                    key,
+                    format_row(&current_row[key_len..])
+                );
+                if self.conflict_policy != ConflictPolicy::Overwrite {
+                    continue;
+                }
+            }
+
+            info!(
+                "  {}: row {} {} -> {}",
+                self.table,
+                key,
+                format_row(&current_row[key_len..]),
+                format_row(&row[key_len..])
+            );
+            reverts.push(row);
+        }
+
+        if self.conflict_policy == ConflictPolicy::Report && conflicting_rows > 0 {
+            return Err(anyhow!(
+                "{} rows changed since the incident. No changes have been made.",
+                conflicting_rows
+            ));
+        }
+
+        let mut reverted_rows = 0;
+        if self.apply {
+            let column_names = columns.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+            let batch_size = (MAX_MUTATIONS_PER_COMMIT / columns.len()).max(1);
+
+            for batch in reverts.chunks(batch_size) {
+                let mutations = batch
+                    .iter()
+                    .map(|row| {
+                        update(
+                            &self.table,
+                            &column_names,
+                            &row.iter().map(|v| v as &dyn ToKind).collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                // Each batch is written only if its rows still hold the values they were
+                // checked for conflicts with.
+                let expected = batch
+                    .iter()
+                    .map(|row| ExpectedRow {
+                        table: self.table.clone(),
+                        columns: columns.clone(),
+                        key: row[..key_len].to_vec(),
+                        values: current.get(&format_row(&row[..key_len])).cloned(),
+                    })
+                    .collect::<Vec<_>>();
+
+                apply_if_unchanged(self.client, &expected, mutations).await?;
+                reverted_rows += batch.len();
+            }
+            info!(
+                "✅ {}: {} rows modified, {} conflicting, {} reverted",
+                self.table, modified_rows, conflicting_rows, reverted_rows
+            );
+        } else {
+            info!(
+                "ℹ️ {}: {} rows modified, {} conflicting, {} to revert (dry run, use --apply to revert)",
+                self.table,
+                modified_rows,
+                conflicting_rows,
+                reverts.len()
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,641 @@
+use anyhow::{anyhow, Result};
+use google_cloud_spanner::client::{Client, ReadOnlyTransactionOption};
+use google_cloud_spanner::key::{Key, KeySet};
+use google_cloud_spanner::reader::AsyncIterator;
+use google_cloud_spanner::statement::{Statement, ToKind};
+use google_cloud_spanner::transaction::Transaction;
+use google_cloud_spanner::transaction_ro::ReadOnlyTransaction;
+use google_cloud_spanner::value::TimestampBound;
+
+use crate::value::{quote_string, SpannerValue};
+
+/// A column of a table, as described by `INFORMATION_SCHEMA.COLUMNS`.
+#[derive(Debug, Clone)]
+pub struct Column {
+    pub name: String,
+    pub spanner_type: String,
+    pub generated: bool,
+}
+
+/// The columns and primary key of a table at a point in time.
+#[derive(Debug, Clone)]
+pub struct TableSchema {
+    pub name: String,
+    pub columns: Vec<Column>,
+    pub primary_key: Vec<String>,
+}
+
+impl TableSchema {
+    /// Read the schema of the named table with the given timestamp bound.
+    pub async fn read(client: &Client, bound: TimestampBound, table: &str) -> Result<TableSchema> {
+        let mut tx = client.single_with_timestamp_bound(bound).await?;
+
+        let mut stmt = Statement::new(
+            "SELECT COLUMN_NAME, SPANNER_TYPE, IS_GENERATED FROM INFORMATION_SCHEMA.COLUMNS \
+             WHERE TABLE_SCHEMA = '' AND TABLE_NAME = @table ORDER BY ORDINAL_POSITION",
+        );
+        stmt.add_param("table", &table);
+        let mut rows = tx.query(stmt).await?;
+        let mut columns = vec![];
+        while let Some(row) = rows.next().await? {
+            columns.push(Column {
+                name: row.column::<String>(0)?,
+                spanner_type: row.column::<String>(1)?,
+                generated: row.column::<String>(2)? == "ALWAYS",
+            });
+        }
+
+        let mut stmt = Statement::new(
+            "SELECT COLUMN_NAME FROM INFORMATION_SCHEMA.INDEX_COLUMNS \
+             WHERE TABLE_SCHEMA = '' AND TABLE_NAME = @table AND INDEX_TYPE = 'PRIMARY_KEY' \
+             ORDER BY ORDINAL_POSITION",
+        );
+        stmt.add_param("table", &table);
+        let mut rows = tx.query(stmt).await?;
+        let mut primary_key = vec![];
+        while let Some(row) = rows.next().await? {
+            primary_key.push(row.column::<String>(0)?);
+        }
+
+        if columns.is_empty() {
+            return Err(anyhow!("Table {} not found.", table));
+        }
+
+        Ok(TableSchema {
+            name: table.to_string(),
+            columns,
+            primary_key,
+        })
+    }
+
+    /// Return the names of the columns which can be written to, primary key columns first.
+    pub fn writable_columns(&self) -> Vec<String> {
+        let mut columns = self.primary_key.clone();
+        columns.extend(
+            self.columns
+                .iter()
+                .filter(|c| !c.generated && !self.primary_key.contains(&c.name))
+                .map(|c| c.name.clone()),
+        );
+        columns
+    }
+}
+
+/// Quote an identifier for use in GoogleSQL.
+pub fn quote_identifier(name: &str) -> String {
+    format!("`{}`", name.replace('`', "\\`"))
+}
+
+/// Run a query with the given timestamp bound, returning every column of every row.
+pub async fn read_rows(
+    client: &Client,
+    bound: TimestampBound,
+    sql: &str,
+    column_count: usize,
+) -> Result<Vec<Vec<SpannerValue>>> {
+    let mut tx = client.single_with_timestamp_bound(bound).await?;
+    let mut rows = tx.query(Statement::new(sql)).await?;
+
+    let mut result = vec![];
+    while let Some(row) = rows.next().await? {
+        result.push(
+            (0..column_count)
+                .map(|i| row.column::<SpannerValue>(i))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+    Ok(result)
+}
+
+/// Run a query with the given timestamp bound, returning the names of the result columns
+/// along with every row.
+pub async fn read_named_rows(
+    client: &Client,
+    bound: TimestampBound,
+    sql: &str,
+) -> Result<(Vec<String>, Vec<Vec<SpannerValue>>)> {
+    let mut tx = client.single_with_timestamp_bound(bound).await?;
+    let mut rows = tx.query(Statement::new(sql)).await?;
+
+    let mut result = vec![];
+    while let Some(row) = rows.next().await? {
+        let column_count = rows.columns_metadata().len();
+        result.push(
+            (0..column_count)
+                .map(|i| row.column::<SpannerValue>(i))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+
+    let columns = rows
+        .columns_metadata()
+        .iter()
+        .map(|field| field.name.clone())
+        .collect();
+    Ok((columns, result))
+}
+
+/// Read the given columns of the rows with the given primary keys, with the given timestamp
+/// bound. Rows which do not exist are omitted from the result.
+pub async fn read_keys(
+    client: &Client,
+    bound: TimestampBound,
+    table: &str,
+    columns: &[String],
+    keys: &[&[SpannerValue]],
+) -> Result<Vec<Vec<SpannerValue>>> {
+    if keys.is_empty() {
+        return Ok(vec![]);
+    }
+    let mut tx = client.single_with_timestamp_bound(bound).await?;
+    Ok(read_keys_in(&mut tx, table, columns, keys).await?)
+}
+
+/// Read the given columns of the rows with the given primary keys within a transaction, such
+/// as a read-write transaction which locks them. Rows which do not exist are omitted from
+/// the result.
+pub async fn read_keys_in(
+    tx: &mut Transaction,
+    table: &str,
+    columns: &[String],
+    keys: &[&[SpannerValue]],
+) -> Result<Vec<Vec<SpannerValue>>, google_cloud_spanner::client::Error> {
+    if keys.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let key_set: KeySet = keys
+        .iter()
+        .map(|key| {
+            Key::composite(
+                &key.iter()
+                    .map(|value| value as &dyn ToKind)
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect::<Vec<_>>()
+        .into();
+
+    let columns = columns.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+    let mut rows = tx.read(table, &columns, key_set).await?;
+
+    let mut result = vec![];
+    while let Some(row) = rows.next().await? {
+        result.push(
+            (0..columns.len())
+                .map(|i| row.column::<SpannerValue>(i))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+    }
+    Ok(result)
+}
+
+/// Read every row of an `INFORMATION_SCHEMA` query whose columns are all cast to `STRING`.
+async fn query_strings(
+    tx: &mut ReadOnlyTransaction,
+    sql: &str,
+    column_count: usize,
+) -> Result<Vec<Vec<String>>> {
+    let mut rows = tx.query(Statement::new(sql)).await?;
+    let mut result = vec![];
+    while let Some(row) = rows.next().await? {
+        result.push(
+            (0..column_count)
+                .map(|i| Ok(row.column::<Option<String>>(i)?.unwrap_or_default()))
+                .collect::<Result<Vec<_>>>()?,
+        );
+    }
+    Ok(result)
+}
+
+/// The schema objects of a database at a point in time, read from `INFORMATION_SCHEMA`.
+/// Each object is held as the DDL statement which creates it, keyed by its kind and name.
+#[derive(Debug, Clone, Default)]
+pub struct DatabaseSchema {
+    pub objects: Vec<(String, String)>,
+}
+
+impl DatabaseSchema {
+    /// Read the schema with the given timestamp bound, from a single consistent snapshot.
+    pub async fn read(client: &Client, bound: TimestampBound) -> Result<DatabaseSchema> {
+        let mut tx = client
+            .read_only_transaction_with_option(ReadOnlyTransactionOption {
+                timestamp_bound: bound,
+                ..Default::default()
+            })
+            .await?;
+
+        let mut schema = DatabaseSchema::default();
+        schema.read_tables(&mut tx).await?;
+        schema.read_indexes(&mut tx).await?;
+        schema.read_foreign_keys(&mut tx).await?;
+        schema.read_views(&mut tx).await?;
+        schema.read_change_streams(&mut tx).await?;
+        Ok(schema)
+    }
+
+    /// Read the tables, with their columns, check constraints and primary keys. Parent tables
+    /// are created before their interleaved children.
+    async fn read_tables(&mut self, tx: &mut ReadOnlyTransaction) -> Result<()> {
+        let tables = query_strings(
+            tx,
+            "SELECT TABLE_NAME, PARENT_TABLE_NAME, ON_DELETE_ACTION, ROW_DELETION_POLICY_EXPRESSION \
+             FROM INFORMATION_SCHEMA.TABLES \
+             WHERE TABLE_SCHEMA = '' AND TABLE_TYPE = 'BASE TABLE' ORDER BY TABLE_NAME",
+            4,
+        )
+        .await?;
+        let columns = query_strings(
+            tx,
+            "SELECT c.TABLE_NAME, c.COLUMN_NAME, c.SPANNER_TYPE, c.IS_NULLABLE, c.COLUMN_DEFAULT, \
+             c.GENERATION_EXPRESSION, c.IS_STORED, o.OPTION_VALUE \
+             FROM INFORMATION_SCHEMA.COLUMNS c \
+             LEFT JOIN INFORMATION_SCHEMA.COLUMN_OPTIONS o \
+             ON o.TABLE_SCHEMA = c.TABLE_SCHEMA AND o.TABLE_NAME = c.TABLE_NAME \
+             AND o.COLUMN_NAME = c.COLUMN_NAME AND o.OPTION_NAME = 'allow_commit_timestamp' \
+             WHERE c.TABLE_SCHEMA = '' ORDER BY c.TABLE_NAME, c.ORDINAL_POSITION",
+            8,
+        )
+        .await?;
+        let checks = query_strings(
+            tx,
+            "SELECT t.TABLE_NAME, t.CONSTRAINT_NAME, c.CHECK_CLAUSE \
+             FROM INFORMATION_SCHEMA.TABLE_CONSTRAINTS t \
+             JOIN INFORMATION_SCHEMA.CHECK_CONSTRAINTS c \
+             ON c.CONSTRAINT_SCHEMA = t.CONSTRAINT_SCHEMA AND c.CONSTRAINT_NAME = t.CONSTRAINT_NAME \
+             WHERE t.TABLE_SCHEMA = '' AND t.CONSTRAINT_TYPE = 'CHECK' \
+             AND NOT STARTS_WITH(t.CONSTRAINT_NAME, 'CK_IS_NOT_NULL_') \
+             ORDER BY t.TABLE_NAME, t.CONSTRAINT_NAME",
+            3,
+        )
+        .await?;
+        let keys = query_strings(
+            tx,
+            "SELECT TABLE_NAME, COLUMN_NAME, COLUMN_ORDERING FROM INFORMATION_SCHEMA.INDEX_COLUMNS \
+             WHERE TABLE_SCHEMA = '' AND INDEX_TYPE = 'PRIMARY_KEY' \
+             ORDER BY TABLE_NAME, ORDINAL_POSITION",
+            3,
+        )
+        .await?;
+
+        // Order the tables so that every parent is created before its children.
+        let mut ordered: Vec<&Vec<String>> = vec![];
+        while ordered.len() < tables.len() {
+            let before = ordered.len();
+            for table in &tables {
+                let parent_created =
+                    table[1].is_empty() || ordered.iter().any(|t| t[0] == table[1]);
+                if parent_created && !ordered.iter().any(|t| t[0] == table[0]) {
+                    ordered.push(table);
+                }
+            }
+            if ordered.len() == before {
+                return Err(anyhow!("Unable to order interleaved tables."));
+            }
+        }
+
+        for table in ordered {
+            let name = &table[0];
+            let mut definitions = columns
+                .iter()
+                .filter(|c| &c[0] == name)
+                .map(|c| {
+                    let mut definition = format!("{} {}", quote_identifier(&c[1]), c[2]);
+                    if c[3] == "NO" {
+                        definition.push_str(" NOT NULL");
+                    }
+                    if !c[4].is_empty() {
+                        definition.push_str(&format!(" DEFAULT ({})", c[4]));
+                    }
+                    if !c[5].is_empty() {
+                        definition.push_str(&format!(" AS ({})", c[5]));
+                        if c[6] == "YES" {
+                            definition.push_str(" STORED");
+                        }
+                    }
+                    if c[7].eq_ignore_ascii_case("TRUE") {
+                        definition.push_str(" OPTIONS (allow_commit_timestamp = true)");
+                    }
+                    definition
+                })
+                .collect::<Vec<_>>();
+            definitions.extend(
+                checks
+                    .iter()
+                    .filter(|c| &c[0] == name)
+                    .map(|c| format!("CONSTRAINT {} CHECK ({})", quote_identifier(&c[1]), c[2])),
+            );
+
+            let mut ddl = format!(
+                "CREATE TABLE {} (\n{}\n) PRIMARY KEY ({})",
+                quote_identifier(name),
+                definitions
+                    .iter()
+                    .map(|d| format!("  {}", d))
+                    .collect::<Vec<_>>()
+                    .join(",\n"),
+                keys.iter()
+                    .filter(|k| &k[0] == name)
+                    .map(|k| format!("{} {}", quote_identifier(&k[1]), k[2]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            if !table[1].is_empty() {
+                ddl.push_str(&format!(
+                    ",\n  INTERLEAVE IN PARENT {} ON DELETE {}",
+                    quote_identifier(&table[1]),
+                    table[2]
+                ));
+            }
+            if !table[3].is_empty() {
+                ddl.push_str(&format!(",\n  ROW DELETION POLICY ({})", table[3]));
+            }
+            self.objects.push((format!("TABLE {}", name), ddl));
+        }
+        Ok(())
+    }
+
+    /// Read the secondary indexes, excluding those managed by Spanner.
+    async fn read_indexes(&mut self, tx: &mut ReadOnlyTransaction) -> Result<()> {
+        let indexes = query_strings(
+            tx,
+            "SELECT TABLE_NAME, INDEX_NAME, CAST(IS_UNIQUE AS STRING), \
+             CAST(IS_NULL_FILTERED AS STRING), PARENT_TABLE_NAME \
+             FROM INFORMATION_SCHEMA.INDEXES \
+             WHERE TABLE_SCHEMA = '' AND INDEX_TYPE = 'INDEX' AND NOT SPANNER_IS_MANAGED \
+             ORDER BY TABLE_NAME, INDEX_NAME",
+            5,
+        )
+        .await?;
+        let columns = query_strings(
+            tx,
+            "SELECT TABLE_NAME, INDEX_NAME, COLUMN_NAME, COLUMN_ORDERING, \
+             CAST(ORDINAL_POSITION AS STRING) \
+             FROM INFORMATION_SCHEMA.INDEX_COLUMNS \
+             WHERE TABLE_SCHEMA = '' AND INDEX_TYPE = 'INDEX' \
+             ORDER BY TABLE_NAME, INDEX_NAME, ORDINAL_POSITION",
+            5,
+        )
+        .await?;
+
+        for index in indexes {
+            let index_columns = columns
+                .iter()
+                .filter(|c| c[0] == index[0] && c[1] == index[1])
+                .collect::<Vec<_>>();
+            let mut ddl = format!(
+                "CREATE {}{}INDEX {} ON {} ({})",
+                if index[2] == "true" { "UNIQUE " } else { "" },
+                if index[3] == "true" {
+                    "NULL_FILTERED "
+                } else {
+                    ""
+                },
+                quote_identifier(&index[1]),
+                quote_identifier(&index[0]),
+                index_columns
+                    .iter()
+                    .filter(|c| !c[4].is_empty())
+                    .map(|c| format!("{} {}", quote_identifier(&c[2]), c[3]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+            // Stored columns have no position in the index key.
+            let storing = index_columns
+                .iter()
+                .filter(|c| c[4].is_empty())
+                .map(|c| quote_identifier(&c[2]))
+                .collect::<Vec<_>>();
+            if !storing.is_empty() {
+                ddl.push_str(&format!(" STORING ({})", storing.join(", ")));
+            }
+            if !index[4].is_empty() {
+                ddl.push_str(&format!(", INTERLEAVE IN {}", quote_identifier(&index[4])));
+            }
+            self.objects.push((format!("INDEX {}", index[1]), ddl));
+        }
+        Ok(())
+    }
+
+    /// Read the foreign keys, which are added once every table has been created.
+    async fn read_foreign_keys(&mut self, tx: &mut ReadOnlyTransaction) -> Result<()> {
+        let columns = query_strings(
+            tx,
+            "SELECT rc.CONSTRAINT_NAME, fk.TABLE_NAME, fk.COLUMN_NAME, pk.TABLE_NAME, \
+             pk.COLUMN_NAME, rc.DELETE_RULE \
+             FROM INFORMATION_SCHEMA.REFERENTIAL_CONSTRAINTS rc \
+             JOIN INFORMATION_SCHEMA.KEY_COLUMN_USAGE fk \
+             ON fk.CONSTRAINT_SCHEMA = rc.CONSTRAINT_SCHEMA AND fk.CONSTRAINT_NAME = rc.CONSTRAINT_NAME \
+             JOIN INFORMATION_SCHEMA.KEY_COLUMN_USAGE pk \
+             ON pk.CONSTRAINT_SCHEMA = rc.UNIQUE_CONSTRAINT_SCHEMA \
+             AND pk.CONSTRAINT_NAME = rc.UNIQUE_CONSTRAINT_NAME \
+             AND pk.ORDINAL_POSITION = fk.POSITION_IN_UNIQUE_CONSTRAINT \
+             WHERE rc.CONSTRAINT_SCHEMA = '' \
+             ORDER BY rc.CONSTRAINT_NAME, fk.ORDINAL_POSITION",
+            6,
+        )
+        .await?;
+
+        let mut names = columns.iter().map(|c| c[0].clone()).collect::<Vec<_>>();
+        names.dedup();
+        for name in names {
+            let fk = columns.iter().filter(|c| c[0] == name).collect::<Vec<_>>();
+            let list = |i: usize| {
+                fk.iter()
+                    .map(|c| quote_identifier(&c[i]))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let mut ddl = format!(
+                "ALTER TABLE {} ADD CONSTRAINT {} FOREIGN KEY ({}) REFERENCES {} ({})",
+                quote_identifier(&fk[0][1]),
+                quote_identifier(&name),
+                list(2),
+                quote_identifier(&fk[0][3]),
+                list(4)
+            );
+            if fk[0][5] == "CASCADE" {
+                ddl.push_str(" ON DELETE CASCADE");
+            }
+            self.objects.push((format!("FOREIGN KEY {}", name), ddl));
+        }
+        Ok(())
+    }
+
+    /// Read the views.
+    async fn read_views(&mut self, tx: &mut ReadOnlyTransaction) -> Result<()> {
+        let views = query_strings(
+            tx,
+            "SELECT TABLE_NAME, VIEW_DEFINITION, IFNULL(SECURITY_TYPE, 'INVOKER') \
+             FROM INFORMATION_SCHEMA.VIEWS WHERE TABLE_SCHEMA = '' ORDER BY TABLE_NAME",
+            3,
+        )
+        .await?;
+
+        for view in views {
+            self.objects.push((
+                format!("VIEW {}", view[0]),
+                format!(
+                    "CREATE VIEW {} SQL SECURITY {} AS {}",
+                    quote_identifier(&view[0]),
+                    view[2],
+                    view[1]
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Read the change streams, with the tables and columns they watch and their options.
+    async fn read_change_streams(&mut self, tx: &mut ReadOnlyTransaction) -> Result<()> {
+        let streams = query_strings(
+            tx,
+            "SELECT CHANGE_STREAM_NAME, CAST(`ALL` AS STRING) FROM INFORMATION_SCHEMA.CHANGE_STREAMS \
+             WHERE CHANGE_STREAM_SCHEMA = '' ORDER BY CHANGE_STREAM_NAME",
+            2,
+        )
+        .await?;
+        let tables = query_strings(
+            tx,
+            "SELECT CHANGE_STREAM_NAME, TABLE_NAME, CAST(ALL_COLUMNS AS STRING) \
+             FROM INFORMATION_SCHEMA.CHANGE_STREAM_TABLES \
+             WHERE CHANGE_STREAM_SCHEMA = '' ORDER BY CHANGE_STREAM_NAME, TABLE_NAME",
+            3,
+        )
+        .await?;
+        let columns = query_strings(
+            tx,
+            "SELECT CHANGE_STREAM_NAME, TABLE_NAME, COLUMN_NAME \
+             FROM INFORMATION_SCHEMA.CHANGE_STREAM_COLUMNS \
+             WHERE CHANGE_STREAM_SCHEMA = '' ORDER BY CHANGE_STREAM_NAME, TABLE_NAME, COLUMN_NAME",
+            3,
+        )
+        .await?;
+        let options = query_strings(
+            tx,
+            "SELECT CHANGE_STREAM_NAME, OPTION_NAME, OPTION_VALUE \
+             FROM INFORMATION_SCHEMA.CHANGE_STREAM_OPTIONS \
+             WHERE CHANGE_STREAM_SCHEMA = '' ORDER BY CHANGE_STREAM_NAME, OPTION_NAME",
+            3,
+        )
+        .await?;
+
+        for stream in streams {
+            let name = &stream[0];
+            let mut ddl = format!("CREATE CHANGE STREAM {}", quote_identifier(name));
+            if stream[1] == "true" {
+                ddl.push_str(" FOR ALL");
+            } else {
+                let watched = tables
+                    .iter()
+                    .filter(|t| &t[0] == name)
+                    .map(|t| {
+                        if t[2] == "true" {
+                            quote_identifier(&t[1])
+                        } else {
+                            format!(
+                                "{}({})",
+                                quote_identifier(&t[1]),
+                                columns
+                                    .iter()
+                                    .filter(|c| &c[0] == name && c[1] == t[1])
+                                    .map(|c| quote_identifier(&c[2]))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if !watched.is_empty() {
+                    ddl.push_str(&format!(" FOR {}", watched.join(", ")));
+                }
+            }
+            let stream_options = options
+                .iter()
+                .filter(|o| &o[0] == name)
+                .map(|o| format!("{} = {}", o[1], quote_string(&o[2])))
+                .collect::<Vec<_>>();
+            if !stream_options.is_empty() {
+                ddl.push_str(&format!(" OPTIONS ({})", stream_options.join(", ")));
+            }
+            self.objects.push((format!("CHANGE STREAM {}", name), ddl));
+        }
+        Ok(())
+    }
+
+    /// Render the schema as a DDL script.
+    pub fn to_ddl(&self) -> String {
+        self.objects
+            .iter()
+            .map(|(_, ddl)| format!("{};", ddl))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Compare this schema with a later one, rendering the objects which were dropped,
+    /// created or changed in between as commented DDL.
+    pub fn diff(&self, later: &DatabaseSchema) -> String {
+        let find = |schema: &DatabaseSchema, key: &str| {
+            schema
+                .objects
+                .iter()
+                .find(|(k, _)| k == key)
+                .map(|(_, ddl)| ddl.clone())
+        };
+
+        let mut sections = vec![];
+        for (key, ddl) in &self.objects {
+            match find(later, key) {
+                None => sections.push(format!("-- Dropped {}:\n{};", key, ddl)),
+                Some(later_ddl) if &later_ddl != ddl => sections.push(format!(
+                    "-- Changed {}, previously:\n{};\n-- Now:\n{};",
+                    key, ddl, later_ddl
+                )),
+                Some(_) => {}
+            }
+        }
+        for (key, ddl) in &later.objects {
+            if find(self, key).is_none() {
+                sections.push(format!("-- Created {}:\n{};", key, ddl));
+            }
+        }
+        sections.join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DatabaseSchema;
+
+    fn schema(objects: &[(&str, &str)]) -> DatabaseSchema {
+        DatabaseSchema {
+            objects: objects
+                .iter()
+                .map(|(key, ddl)| (key.to_string(), ddl.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Test that dropped, changed and created objects are reported with their DDL.
+    #[test]
+    fn test_diff() {
+        let before = schema(&[
+            ("TABLE A", "CREATE TABLE A"),
+            ("TABLE B", "CREATE TABLE B"),
+            ("INDEX C", "CREATE INDEX C"),
+        ]);
+        let after = schema(&[
+            ("TABLE A", "CREATE TABLE A"),
+            ("INDEX C", "CREATE UNIQUE INDEX C"),
+            ("VIEW D", "CREATE VIEW D"),
+        ]);
+
+        assert_eq!(
+            before.diff(&after),
+            "-- Dropped TABLE B:\nCREATE TABLE B;\n\n\
+             -- Changed INDEX C, previously:\nCREATE INDEX C;\n-- Now:\nCREATE UNIQUE INDEX C;\n\n\
+             -- Created VIEW D:\nCREATE VIEW D;"
+        );
+        assert_eq!(after.diff(&after), "");
+    }
+}
@@ -0,0 +1,340 @@
+use std::future::Future;
+
+use anyhow::anyhow;
+use clap::{arg, Args, ValueEnum};
+use google_cloud_gax::grpc::{Code, Status};
+use log::{debug, warn};
+use time::OffsetDateTime;
+
+/// The initial delay before retrying a query which failed with a transient error.
+const INITIAL_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+/// Fragments of the messages Spanner returns when a query refers to a table, column, index
+/// or view which does not exist at the read timestamp.
+const SCHEMA_MISSING_MESSAGES: [&str; 5] = [
+    "table not found:",
+    "column not found:",
+    "index not found",
+    "does not have a secondary index",
+    "view not found",
+];
+
+/// Fragment of the message Spanner returns for a name which cannot be resolved. This is a
+/// dropped column only once the names of the query are known to resolve at some timestamp,
+/// and is otherwise taken to be a mistake in the query, such as a typo.
+const UNRECOGNIZED_NAME: &str = "unrecognized name";
+
+/// Start of the messages the PostgreSQL dialect returns when a query refers to a table or
+/// column which does not exist, such as `relation "singers" does not exist`.
+const SCHEMA_MISSING_POSTGRESQL: [&str; 2] = ["relation \"", "column \""];
+
+/// Return whether a lower case error message says a table, column, index or view does not
+/// exist.
+fn schema_missing(message: &str) -> bool {
+    SCHEMA_MISSING_MESSAGES
+        .iter()
+        .any(|fragment| message.contains(fragment))
+        || SCHEMA_MISSING_POSTGRESQL.iter().any(|prefix| {
+            message.find(prefix).is_some_and(|i| {
+                message[i + prefix.len()..]
+                    .split_once('"')
+                    .is_some_and(|(_, rest)| rest.starts_with(" does not exist"))
+            })
+        })
+}
+
+/// The kind of failure of a check query, classified from its gRPC status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum QueryError {
+    /// A table, column, index or view referred to by the query does not exist
+    SchemaMissing,
+    /// The read timestamp is older than the version retention period
+    StalenessExceeded,
+    /// The caller is not allowed to run the query
+    PermissionDenied,
+    /// Spanner is unavailable or overloaded, or the query was aborted or timed out
+    Transient,
+    /// The query is not valid, such as a syntax error
+    InvalidQuery,
+    /// Any other failure
+    Other,
+}
+
+impl QueryError {
+    /// Classify a gRPC status returned by Spanner. An unrecognized name is only a missing
+    /// column if the names of the query have been resolved at another timestamp.
+    pub fn from_status(status: &Status, names_resolved: bool) -> Self {
+        let message = status.message().to_lowercase();
+        match status.code() {
+            Code::PermissionDenied | Code::Unauthenticated => QueryError::PermissionDenied,
+            _ if message.contains("exceeded the maximum timestamp staleness") => {
+                QueryError::StalenessExceeded
+            }
+            Code::NotFound | Code::InvalidArgument if schema_missing(&message) => {
+                QueryError::SchemaMissing
+            }
+            Code::InvalidArgument if names_resolved && message.contains(UNRECOGNIZED_NAME) => {
+                QueryError::SchemaMissing
+            }
+            Code::Unavailable
+            | Code::DeadlineExceeded
+            | Code::Aborted
+            | Code::ResourceExhausted => QueryError::Transient,
+            Code::InvalidArgument => QueryError::InvalidQuery,
+            _ => QueryError::Other,
+        }
+    }
+
+    /// Classify an error returned while running a query.
+    pub fn from_error(e: &anyhow::Error, names_resolved: bool) -> Self {
+        if let Some(status) = e.downcast_ref::<Status>() {
+            return Self::from_status(status, names_resolved);
+        }
+        match e.downcast_ref::<google_cloud_spanner::client::Error>() {
+            Some(google_cloud_spanner::client::Error::GRPC(status)) => {
+                Self::from_status(status, names_resolved)
+            }
+            _ => QueryError::Other,
+        }
+    }
+}
+
+/// Command line options controlling how failures of the check query are treated during a
+/// search. A failure which is not `false`, retried or fatal leaves the probe unknown.
+#[derive(Args, Debug, Clone)]
+pub struct ErrorOptions {
+    /// Failures which mean the check query returned `false` (ignored with `--polarity
+    /// false-to-true`, where they leave the probe unknown)
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [QueryError::SchemaMissing])]
+    pub false_on: Vec<QueryError>,
+    /// Failures which are retried with exponential backoff
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [QueryError::Transient])]
+    pub retry_on: Vec<QueryError>,
+    /// Failures which stop the search
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [QueryError::PermissionDenied, QueryError::InvalidQuery])]
+    pub fatal_on: Vec<QueryError>,
+    /// Number of times to retry a failed check query
+    #[arg(long, default_value_t = 5)]
+    pub retries: u32,
+    /// Whether the query has run successfully at some timestamp, so its names resolve
+    #[arg(skip)]
+    pub names_resolved: bool,
+}
+
+impl Default for ErrorOptions {
+    fn default() -> Self {
+        ErrorOptions {
+            false_on: vec![QueryError::SchemaMissing],
+            retry_on: vec![QueryError::Transient],
+            fatal_on: vec![QueryError::PermissionDenied, QueryError::InvalidQuery],
+            retries: 5,
+            names_resolved: false,
+        }
+    }
+}
+
+impl ErrorOptions {
+    /// Check that no kind of failure is given more than one treatment.
+    pub fn check(&self) -> anyhow::Result<()> {
+        for kind in QueryError::value_variants() {
+            let lists = [&self.false_on, &self.retry_on, &self.fatal_on]
+                .iter()
+                .filter(|list| list.contains(kind))
+                .count();
+            if lists > 1 {
+                return Err(anyhow!(
+                    "{} is listed in more than one of --false-on, --retry-on and --fatal-on.",
+                    kind.to_possible_value()
+                        .map(|value| value.get_name().to_string())
+                        .unwrap_or_default()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Classify an error returned while running the query.
+    pub fn classify(&self, e: &anyhow::Error) -> QueryError {
+        QueryError::from_error(e, self.names_resolved)
+    }
+
+    /// Run the query at each of the given timestamps until it succeeds, to check that its
+    /// names resolve. Until they do, an unrecognized name is treated as an invalid query
+    /// rather than a missing column.
+    pub async fn resolve_names<T, F, Fut>(&mut self, timestamps: &[OffsetDateTime], mut query: F)
+    where
+        F: FnMut(OffsetDateTime) -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        for ts in timestamps {
+            match query(*ts).await {
+                Ok(_) => {
+                    self.names_resolved = true;
+                    return;
+                }
+                Err(e) => debug!("Query failed at {}: {}", ts, e),
+            }
+        }
+        warn!(
+            "Query failed at both ends of the window. Unrecognized names will be treated as \
+             mistakes in the query."
+        );
+    }
+
+    /// Run a query at a specific timestamp, retrying failures with exponential backoff as
+    /// configured. A fatal failure is returned as an error, and a failure which persists,
+    /// or is neither retried nor fatal, is returned as the outcome with its kind.
+    pub async fn retry<T, F, Fut>(
+        &self,
+        ts: &OffsetDateTime,
+        mut query: F,
+    ) -> anyhow::Result<Result<T, (QueryError, anyhow::Error)>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        let mut delay = INITIAL_RETRY_DELAY;
+        let mut attempt = 0;
+        loop {
+            let e = match query().await {
+                Ok(value) => return Ok(Ok(value)),
+                Err(e) => e,
+            };
+
+            let kind = self.classify(&e);
+            if self.fatal_on.contains(&kind) {
+                return Err(anyhow!("Query failed at {}: {}", ts, e));
+            }
+            if attempt >= self.retries || !self.retry_on.contains(&kind) {
+                debug!("Query at {} failed with {:?}.", ts, kind);
+                return Ok(Err((kind, e)));
+            }
+            warn!(
+                "Query at {} failed ({}). Retrying in {}ms...",
+                ts,
+                e,
+                delay.as_millis()
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_cloud_gax::grpc::{Code, Status};
+
+    use super::{ErrorOptions, QueryError};
+
+    /// Test that statuses are classified by their code and message, in either dialect.
+    #[test]
+    fn test_from_status() {
+        let classify = |code, message| QueryError::from_status(&Status::new(code, message), false);
+
+        assert_eq!(
+            classify(Code::InvalidArgument, "Table not found: Singers [at 1:22]"),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(Code::NotFound, "Column not found: FirstName"),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(Code::NotFound, "relation \"singers\" does not exist"),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(Code::InvalidArgument, "column \"firstname\" does not exist"),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(Code::InvalidArgument, "Index not found: SingersByName"),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(
+                Code::InvalidArgument,
+                "Table Singers does not have a secondary index called SingersByName"
+            ),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(Code::InvalidArgument, "View not found: SingerNames"),
+            QueryError::SchemaMissing
+        );
+        assert_eq!(
+            classify(
+                Code::InvalidArgument,
+                "Unrecognized name: FirstNmae [at 1:8]"
+            ),
+            QueryError::InvalidQuery
+        );
+        assert_eq!(
+            classify(
+                Code::InvalidArgument,
+                "function cont(bigint) does not exist"
+            ),
+            QueryError::InvalidQuery
+        );
+        assert_eq!(
+            classify(Code::NotFound, "Database not found: test-db"),
+            QueryError::Other
+        );
+        assert_eq!(
+            classify(
+                Code::FailedPrecondition,
+                "Read-only transaction timestamp has exceeded the maximum timestamp staleness"
+            ),
+            QueryError::StalenessExceeded
+        );
+        assert_eq!(
+            classify(Code::PermissionDenied, "Caller is missing IAM permission"),
+            QueryError::PermissionDenied
+        );
+        assert_eq!(
+            classify(Code::Unavailable, "Transport closed"),
+            QueryError::Transient
+        );
+        assert_eq!(
+            classify(
+                Code::InvalidArgument,
+                "Syntax error: Unexpected end of script"
+            ),
+            QueryError::InvalidQuery
+        );
+        assert_eq!(
+            classify(Code::Internal, "Internal error"),
+            QueryError::Other
+        );
+    }
+
+    /// Test that an unrecognized name is a missing column only once the names of the query
+    /// have resolved.
+    #[test]
+    fn test_unrecognized_name() {
+        let status = Status::new(
+            Code::InvalidArgument,
+            "Unrecognized name: FirstName [at 1:8]",
+        );
+        assert_eq!(
+            QueryError::from_status(&status, false),
+            QueryError::InvalidQuery
+        );
+        assert_eq!(
+            QueryError::from_status(&status, true),
+            QueryError::SchemaMissing
+        );
+    }
+
+    /// Test that a kind of failure cannot be given more than one treatment.
+    #[test]
+    fn test_check() {
+        let mut errors = ErrorOptions::default();
+        assert!(errors.check().is_ok());
+
+        errors.retry_on.push(QueryError::SchemaMissing);
+        assert!(errors.check().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use anyhow::Result;
+use async_recursion::async_recursion;
+use google_cloud_spanner::client::Client;
+use indicatif::ProgressBar;
+use log::{debug, info, trace, warn};
+use time::{ext::NumericalDuration, OffsetDateTime};
+
+use crate::schema::read_named_rows;
+use crate::status::ErrorOptions;
+use crate::value::format_row;
+use crate::{read_timestamp, ProbeResult};
+
+/// A change in the value of the timeline query, located to within the search accuracy.
+#[derive(Debug, Clone)]
+pub struct ChangePoint {
+    /// Latest timestamp known to return the old value
+    pub before: OffsetDateTime,
+    /// Earliest timestamp known to return the new value
+    pub after: OffsetDateTime,
+    pub old_value: String,
+    pub new_value: String,
+}
+
+/// Logic to find every timestamp at which the result of a query changes. The result can be
+/// any value, such as a row count, a checksum or a status string.
+///
+/// The window is bisected wherever the value differs between the two ends of an interval,
+/// so a value which changes and then changes back between two probes is not detected.
+///
+/// Failures of the query are classified as in a timestamp search: a failure treated as
+/// `false` becomes the value `false`, and one which cannot be decided becomes an unknown
+/// value, so a table being dropped shows up as a change rather than ending the timeline.
+pub struct TimelineFinder<'a> {
+    pub client: &'a Client,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub accuracy: time::Duration,
+    pub limit: usize,
+    pub query: String,
+    pub errors: ErrorOptions,
+}
+
+impl<'a> TimelineFinder<'a> {
+    /// Run the query at a specific timestamp, rendering every row of the result as one value.
+    async fn value_at(&self, ts: &OffsetDateTime) -> Result<String> {
+        let outcome = self
+            .errors
+            .retry(ts, || {
+                read_named_rows(self.client, read_timestamp(ts), &self.query)
+            })
+            .await?;
+        Ok(match outcome {
+            Ok((_, rows)) => rows
+                .iter()
+                .map(|row| format_row(row))
+                .collect::<Vec<_>>()
+                .join(", "),
+            Err((kind, _)) if self.errors.false_on.contains(&kind) => {
+                ProbeResult::False.to_string()
+            }
+            Err((kind, e)) => {
+                warn!("Query could not be decided at {} ({}).", ts, e);
+                ProbeResult::Unknown(format!("{:?}", kind)).to_string()
+            }
+        })
+    }
+
+    /// Find the change points between two timestamps whose values are already known, in
+    /// chronological order, until the limit is reached.
+    #[async_recursion]
+    async fn find_changes(
+        &self,
+        start: (OffsetDateTime, String),
+        end: (OffsetDateTime, String),
+        changes: &mut Vec<ChangePoint>,
+        bar: &ProgressBar,
+    ) -> Result<()> {
+        if start.1 == end.1 || changes.len() >= self.limit {
+            return Ok(());
+        }
+
+        if (end.0 - start.0) < self.accuracy || (end.0 - start.0) <= 1.nanoseconds() {
+            trace!("  Change found between {} and {}", start.0, end.0);
+            changes.push(ChangePoint {
+                before: start.0,
+                after: end.0,
+                old_value: start.1,
+                new_value: end.1,
+            });
+            bar.set_message(format!("{} changes found", changes.len()));
+            return Ok(());
+        }
+
+        let midpoint = start.0.saturating_add((end.0 - start.0) / 2);
+        debug!(
+            "Querying between {} and {} at {}...",
+            start.0, end.0, midpoint
+        );
+        let value = self.value_at(&midpoint).await?;
+        bar.inc(1);
+
+        self.find_changes(start, (midpoint, value.clone()), changes, bar)
+            .await?;
+        self.find_changes((midpoint, value), end, changes, bar)
+            .await
+    }
+
+    /// Execute the timeline search.
+    pub async fn run(&mut self) -> Result<Vec<ChangePoint>> {
+        // A name which cannot be resolved at either end of the window is a mistake in the
+        // query rather than a column which was dropped.
+        let timestamps = [self.end, self.start];
+        self.errors
+            .resolve_names(&timestamps, |ts| {
+                read_named_rows(self.client, read_timestamp(&ts), &self.query)
+            })
+            .await;
+
+        info!(
+            "❔ Querying at start ({}) and end ({}) timestamps...",
+            self.start, self.end
+        );
+        let start_value = self.value_at(&self.start).await?;
+        let end_value = self.value_at(&self.end).await?;
+
+        let bar = ProgressBar::new_spinner();
+        info!("❔ Searching for changes...");
+        let mut changes = vec![];
+        self.find_changes(
+            (self.start, start_value),
+            (self.end, end_value),
+            &mut changes,
+            &bar,
+        )
+        .await?;
+        bar.finish();
+
+        info!("✅ Found {} changes", changes.len());
+        if changes.len() >= self.limit {
+            info!(
+                "ℹ️ Stopped after {} changes, later changes may not be listed",
+                self.limit
+            );
+        }
+        Ok(changes)
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use google_cloud_spanner::client::Client;
+use google_cloud_spanner::mutation::insert;
+use google_cloud_spanner::statement::ToKind;
+use google_cloud_spanner::value::TimestampBound;
+use log::info;
+use time::OffsetDateTime;
+
+use crate::read_timestamp;
+use crate::schema::{quote_identifier, read_keys, read_rows, TableSchema};
+use crate::value::format_row;
+
+/// Maximum number of column values written in a single commit. Spanner limits the number of
+/// mutations per commit, so large writes are split into batches.
+pub const MAX_MUTATIONS_PER_COMMIT: usize = 20_000;
+
+/// Result of undeleting rows from a single table.
+#[derive(Debug)]
+pub struct UndeleteSummary {
+    pub table: String,
+    pub historical_rows: usize,
+    pub missing_rows: usize,
+    pub inserted_rows: usize,
+}
+
+/// Logic to re-insert rows which existed at a historical timestamp but have since been deleted.
+pub struct Undeleter<'a> {
+    pub client: &'a Client,
+    pub tables: Vec<String>,
+    pub filter: String,
+    pub read_timestamp: OffsetDateTime,
+    pub apply: bool,
+}
+
+impl<'a> Undeleter<'a> {
+    /// Undelete the rows of a single table.
+    async fn undelete_table(&self, table: &str) -> Result<UndeleteSummary> {
+        let bound = read_timestamp(&self.read_timestamp);
+        let schema = TableSchema::read(self.client, bound.clone(), table).await?;
+        let columns = schema.writable_columns();
+        let key_len = schema.primary_key.len();
+
+        let sql = format!(
+            "SELECT {} FROM {} WHERE {}",
+            columns
+                .iter()
+                .map(|c| quote_identifier(c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            quote_identifier(table),
+            self.filter
+        );
+        let historical = read_rows(self.client, bound, &sql, columns.len()).await?;
+
+        let keys = historical
+            .iter()
+            .map(|row| &row[..key_len])
+            .collect::<Vec<_>>();
+        let existing = read_keys(
+            self.client,
+            TimestampBound::strong_read(),
+            table,
+            &schema.primary_key,
+            &keys,
+        )
+        .await?
+        .iter()
+        .map(|key| format_row(key))
+        .collect::<HashSet<_>>();
+
+        let missing = historical
+            .iter()
+            .filter(|row| !existing.contains(&format_row(&row[..key_len])))
+            .collect::<Vec<_>>();
+
+        for row in &missing {
+            info!("  {}: missing row {}", table, format_row(&row[..key_len]));
+        }
+
+        let mut inserted_rows = 0;
+        if self.apply {
+            let column_names = columns.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+            let batch_size = (MAX_MUTATIONS_PER_COMMIT / columns.len()).max(1);
+
+            for batch in missing.chunks(batch_size) {
+                let mutations = batch
+                    .iter()
+                    .map(|row| {
+                        insert(
+                            table,
+                            &column_names,
+                            &row.iter().map(|v| v as &dyn ToKind).collect::<Vec<_>>(),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+
+                self.client.apply(mutations).await?;
+                inserted_rows += batch.len();
+            }
+        }
+
+        Ok(UndeleteSummary {
+            table: table.to_string(),
+            historical_rows: historical.len(),
+            missing_rows: missing.len(),
+            inserted_rows,
+        })
+    }
+
+    /// Execute the undelete across every table, in the order given. Parent tables must be
+    /// listed before their interleaved children.
+    pub async fn run(&self) -> Result<Vec<UndeleteSummary>> {
+        info!(
+            "❔ Reading rows matching `{}` at {}...",
+            self.filter, self.read_timestamp
+        );
+
+        let mut summaries = vec![];
+        for table in &self.tables {
+            let summary = self.undelete_table(table).await?;
+            if self.apply {
+                info!(
+                    "✅ {}: {} rows at timestamp, {} missing, {} inserted",
+                    summary.table,
+                    summary.historical_rows,
+                    summary.missing_rows,
+                    summary.inserted_rows
+                );
+            } else {
+                info!(
+                    "ℹ️ {}: {} rows at timestamp, {} missing (dry run, use --apply to insert)",
+                    summary.table, summary.historical_rows, summary.missing_rows
+                );
+            }
+            summaries.push(summary);
+        }
+        Ok(summaries)
+    }
+}
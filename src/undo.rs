@@ -0,0 +1,197 @@
+use anyhow::Result;
+use google_cloud_spanner::client::Client;
+use log::info;
+use time::OffsetDateTime;
+
+use crate::diff::{ChangeType, Differ, TableDiff};
+use crate::read_timestamp;
+use crate::schema::{quote_identifier, TableSchema};
+use crate::value::SpannerValue;
+
+/// Build a condition matching a row by its key values.
+fn key_condition(diff: &TableDiff, key: &[SpannerValue]) -> Result<String> {
+    Ok(diff
+        .key_columns
+        .iter()
+        .zip(key)
+        .map(|(&i, value)| {
+            let column = quote_identifier(&diff.columns[i]);
+            if value.is_null() {
+                Ok(format!("{} IS NULL", column))
+            } else {
+                Ok(format!("{} = {}", column, value.to_sql_literal()?))
+            }
+        })
+        .collect::<Result<Vec<_>>>()?
+        .join(" AND "))
+}
+
+/// Build the DML statements which take the rows of a table from their later state in the
+/// diff back to their earlier state. Generated columns are never written.
+pub fn undo_statements(table: &str, diff: &TableDiff, generated: &[String]) -> Result<Vec<String>> {
+    let table = quote_identifier(table);
+    let writable = (0..diff.columns.len())
+        .filter(|&i| !generated.contains(&diff.columns[i]))
+        .collect::<Vec<_>>();
+
+    let mut deletes = vec![];
+    let mut updates = vec![];
+    let mut inserts = vec![];
+    for row in &diff.rows {
+        match (row.change_type(), &row.before) {
+            (ChangeType::Inserted, _) => deletes.push(format!(
+                "DELETE FROM {} WHERE {};",
+                table,
+                key_condition(diff, &row.key)?
+            )),
+            (ChangeType::Modified, Some(before)) => {
+                let assignments = row
+                    .changed_columns()
+                    .into_iter()
+                    .filter(|i| writable.contains(i) && !diff.key_columns.contains(i))
+                    .map(|i| {
+                        Ok(format!(
+                            "{} = {}",
+                            quote_identifier(&diff.columns[i]),
+                            before[i].to_sql_literal()?
+                        ))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                if !assignments.is_empty() {
+                    updates.push(format!(
+                        "UPDATE {} SET {} WHERE {};",
+                        table,
+                        assignments.join(", "),
+                        key_condition(diff, &row.key)?
+                    ));
+                }
+            }
+            (ChangeType::Deleted, Some(before)) => inserts.push(format!(
+                "INSERT INTO {} ({}) VALUES ({});",
+                table,
+                writable
+                    .iter()
+                    .map(|&i| quote_identifier(&diff.columns[i]))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                writable
+                    .iter()
+                    .map(|&i| before[i].to_sql_literal())
+                    .collect::<Result<Vec<_>>>()?
+                    .join(", ")
+            )),
+            _ => {}
+        }
+    }
+
+    Ok(deletes.into_iter().chain(updates).chain(inserts).collect())
+}
+
+/// Logic to generate a reviewable SQL script which returns a table to its state at a
+/// recovery timestamp, without applying it.
+pub struct UndoScriptGenerator<'a> {
+    pub client: &'a Client,
+    pub table: String,
+    pub filter: String,
+    pub read_timestamp: OffsetDateTime,
+}
+
+impl<'a> UndoScriptGenerator<'a> {
+    /// Execute the comparison and build the script.
+    pub async fn run(&self) -> Result<String> {
+        let schema = TableSchema::read(
+            self.client,
+            read_timestamp(&self.read_timestamp),
+            &self.table,
+        )
+        .await?;
+        let generated = schema
+            .columns
+            .iter()
+            .filter(|c| c.generated)
+            .map(|c| c.name.clone())
+            .collect::<Vec<_>>();
+
+        let diff = Differ {
+            client: self.client,
+            table: Some(self.table.clone()),
+            query: None,
+            key: vec![],
+            filter: self.filter.clone(),
+            from: self.read_timestamp,
+            to: None,
+        }
+        .run()
+        .await?;
+
+        let statements = undo_statements(&self.table, &diff, &generated)?;
+        let (inserted, deleted, modified) = diff.counts();
+        info!("✅ Generated {} undo statements", statements.len());
+
+        let mut script = vec![
+            format!("-- Undo script for table {}.", self.table),
+            format!(
+                "-- Returns the rows matching `{}` to their state at {}.",
+                self.filter,
+                self.read_timestamp
+                    .format(&time::format_description::well_known::Rfc3339)?
+            ),
+            format!(
+                "-- Rows inserted since: {}, deleted since: {}, modified since: {}.",
+                inserted, deleted, modified
+            ),
+        ];
+        script.extend(statements);
+        Ok(script.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use google_cloud_googleapis::spanner::v1::{Type, TypeCode};
+    use prost_types::value::Kind;
+
+    use super::undo_statements;
+    use crate::diff::TableDiff;
+    use crate::value::SpannerValue;
+
+    fn row(id: &str, name: &str) -> Vec<SpannerValue> {
+        vec![
+            SpannerValue {
+                kind: Kind::StringValue(id.to_string()),
+                r#type: Type {
+                    code: TypeCode::Int64.into(),
+                    ..Default::default()
+                },
+            },
+            SpannerValue {
+                kind: Kind::StringValue(name.to_string()),
+                r#type: Type {
+                    code: TypeCode::String.into(),
+                    ..Default::default()
+                },
+            },
+        ]
+    }
+
+    /// Test that each kind of change is undone with the matching statement.
+    #[test]
+    fn test_undo_statements() {
+        let diff = TableDiff::compute(
+            vec!["id".to_string(), "name".to_string()],
+            vec![0],
+            vec![row("1", "a"), row("2", "b"), row("3", "c")],
+            vec![row("1", "a"), row("3", "d"), row("4", "e")],
+        )
+        .unwrap();
+
+        assert_eq!(
+            undo_statements("Singers", &diff, &[]).unwrap(),
+            vec![
+                "DELETE FROM `Singers` WHERE `id` = 4;",
+                "UPDATE `Singers` SET `name` = 'c' WHERE `id` = 3;",
+                "INSERT INTO `Singers` (`id`, `name`) VALUES (2, 'b');",
+            ]
+        );
+    }
+}
@@ -0,0 +1,385 @@
+use std::fmt::Display;
+
+use anyhow::{anyhow, Result};
+use google_cloud_googleapis::spanner::v1::{struct_type::Field, Type, TypeCode};
+use google_cloud_spanner::row::{Error, TryFromValue};
+use google_cloud_spanner::statement::ToKind;
+use prost_types::value::Kind;
+
+/// A column value of any Spanner type, kept in its wire representation so that it can be
+/// written back to the database unchanged.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannerValue {
+    pub kind: Kind,
+    pub r#type: Type,
+}
+
+impl SpannerValue {
+    /// Return whether the value is `NULL`.
+    pub fn is_null(&self) -> bool {
+        matches!(self.kind, Kind::NullValue(_))
+    }
+
+    /// Convert the value to JSON. `INT64` values are written as numbers, `JSON` values are
+    /// embedded, and other types use their string representation.
+    pub fn to_json(&self) -> serde_json::Value {
+        Self::kind_to_json(&self.kind, &self.r#type)
+    }
+
+    /// Convert a value of the given type to JSON.
+    fn kind_to_json(kind: &Kind, r#type: &Type) -> serde_json::Value {
+        match kind {
+            Kind::NullValue(_) => serde_json::Value::Null,
+            Kind::BoolValue(b) => serde_json::Value::Bool(*b),
+            Kind::NumberValue(n) => serde_json::Number::from_f64(*n)
+                .map(serde_json::Value::Number)
+                .unwrap_or_else(|| serde_json::Value::String(n.to_string())),
+            Kind::StringValue(s) => match TypeCode::from_i32(r#type.code) {
+                Some(TypeCode::Int64) => s
+                    .parse::<i64>()
+                    .map(serde_json::Value::from)
+                    .unwrap_or_else(|_| serde_json::Value::String(s.clone())),
+                Some(TypeCode::Json) => {
+                    serde_json::from_str(s).unwrap_or_else(|_| serde_json::Value::String(s.clone()))
+                }
+                _ => serde_json::Value::String(s.clone()),
+            },
+            Kind::ListValue(list) => {
+                let element_type = r#type
+                    .array_element_type
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default();
+                serde_json::Value::Array(
+                    list.values
+                        .iter()
+                        .map(|value| match &value.kind {
+                            Some(kind) => Self::kind_to_json(kind, &element_type),
+                            None => serde_json::Value::Null,
+                        })
+                        .collect(),
+                )
+            }
+            Kind::StructValue(s) => serde_json::Value::Object(
+                s.fields
+                    .iter()
+                    .map(|(name, value)| {
+                        let value = match &value.kind {
+                            Some(kind) => Self::kind_to_json(kind, &Type::default()),
+                            None => serde_json::Value::Null,
+                        };
+                        (name.clone(), value)
+                    })
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Render the value as a GoogleSQL literal of its type.
+    pub fn to_sql_literal(&self) -> Result<String> {
+        Self::kind_to_sql(&self.kind, &self.r#type)
+    }
+
+    /// Render a value of the given type as a GoogleSQL literal.
+    fn kind_to_sql(kind: &Kind, r#type: &Type) -> Result<String> {
+        let code = TypeCode::from_i32(r#type.code);
+        Ok(match (kind, code) {
+            (Kind::NullValue(_), _) => "NULL".to_string(),
+            (Kind::BoolValue(b), _) => b.to_string().to_uppercase(),
+            (Kind::NumberValue(n), _) => Self::float_literal(*n),
+            (Kind::StringValue(s), Some(TypeCode::Int64)) => s.clone(),
+            (Kind::StringValue(s), Some(TypeCode::Float64)) => match s.as_str() {
+                "NaN" => "CAST('nan' AS FLOAT64)".to_string(),
+                "Infinity" => "CAST('inf' AS FLOAT64)".to_string(),
+                "-Infinity" => "CAST('-inf' AS FLOAT64)".to_string(),
+                _ => Self::float_literal(s.parse()?),
+            },
+            (Kind::StringValue(s), Some(TypeCode::Numeric)) => format!("NUMERIC '{}'", s),
+            (Kind::StringValue(s), Some(TypeCode::Bytes)) => {
+                format!("FROM_BASE64({})", quote_string(s))
+            }
+            (Kind::StringValue(s), Some(TypeCode::Date)) => format!("DATE {}", quote_string(s)),
+            (Kind::StringValue(s), Some(TypeCode::Timestamp)) => {
+                format!("TIMESTAMP {}", quote_string(s))
+            }
+            (Kind::StringValue(s), Some(TypeCode::Json)) => format!("JSON {}", quote_string(s)),
+            (Kind::StringValue(s), _) => quote_string(s),
+            (Kind::ListValue(list), _) => {
+                let element_type = r#type
+                    .array_element_type
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default();
+                format!(
+                    "ARRAY<{}>[{}]",
+                    type_name(&element_type)?,
+                    list.values
+                        .iter()
+                        .map(|value| match &value.kind {
+                            Some(kind) => Self::kind_to_sql(kind, &element_type),
+                            None => Ok("NULL".to_string()),
+                        })
+                        .collect::<Result<Vec<_>>>()?
+                        .join(", ")
+                )
+            }
+            (Kind::StructValue(_), _) => {
+                return Err(anyhow!("STRUCT values cannot be written as literals."))
+            }
+        })
+    }
+
+    /// Render a finite or non-finite float as a `FLOAT64` literal.
+    fn float_literal(n: f64) -> String {
+        if n.is_nan() {
+            "CAST('nan' AS FLOAT64)".to_string()
+        } else if n.is_infinite() {
+            format!("CAST('{}inf' AS FLOAT64)", if n < 0.0 { "-" } else { "" })
+        } else {
+            // The debug representation always includes a decimal point or exponent.
+            format!("{:?}", n)
+        }
+    }
+
+    /// Format a value of the given type, quoting values which are not numeric or boolean.
+    fn fmt_kind(kind: &Kind, r#type: &Type, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match kind {
+            Kind::NullValue(_) => f.write_str("NULL"),
+            Kind::BoolValue(b) => write!(f, "{}", b),
+            Kind::NumberValue(n) => write!(f, "{}", n),
+            Kind::StringValue(s) => match TypeCode::from_i32(r#type.code) {
+                Some(TypeCode::Int64 | TypeCode::Float64 | TypeCode::Numeric) => f.write_str(s),
+                _ => write!(f, "{:?}", s),
+            },
+            Kind::ListValue(list) => {
+                let element_type = r#type
+                    .array_element_type
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default();
+                f.write_str("[")?;
+                for (i, value) in list.values.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(", ")?;
+                    }
+                    match &value.kind {
+                        Some(kind) => Self::fmt_kind(kind, &element_type, f)?,
+                        None => f.write_str("NULL")?,
+                    }
+                }
+                f.write_str("]")
+            }
+            Kind::StructValue(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+impl Display for SpannerValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Self::fmt_kind(&self.kind, &self.r#type, f)
+    }
+}
+
+impl TryFromValue for SpannerValue {
+    fn try_from(value: &prost_types::Value, field: &Field) -> std::result::Result<Self, Error> {
+        Ok(SpannerValue {
+            kind: value.kind.clone().unwrap_or(Kind::NullValue(0)),
+            r#type: field.r#type.clone().unwrap_or_default(),
+        })
+    }
+}
+
+impl ToKind for SpannerValue {
+    fn to_kind(&self) -> Kind {
+        self.kind.clone()
+    }
+
+    // Only used when binding statement parameters, which requires a static type.
+    // Mutations and keys are built from `to_kind` alone.
+    fn get_type() -> Type {
+        Type {
+            code: TypeCode::String.into(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Return the GoogleSQL name of a type.
+fn type_name(r#type: &Type) -> Result<String> {
+    Ok(match TypeCode::from_i32(r#type.code) {
+        Some(TypeCode::Bool) => "BOOL".to_string(),
+        Some(TypeCode::Int64) => "INT64".to_string(),
+        Some(TypeCode::Float64) => "FLOAT64".to_string(),
+        Some(TypeCode::Numeric) => "NUMERIC".to_string(),
+        Some(TypeCode::String) => "STRING".to_string(),
+        Some(TypeCode::Bytes) => "BYTES".to_string(),
+        Some(TypeCode::Date) => "DATE".to_string(),
+        Some(TypeCode::Timestamp) => "TIMESTAMP".to_string(),
+        Some(TypeCode::Json) => "JSON".to_string(),
+        Some(TypeCode::Array) => format!(
+            "ARRAY<{}>",
+            type_name(
+                &r#type
+                    .array_element_type
+                    .as_deref()
+                    .cloned()
+                    .unwrap_or_default()
+            )?
+        ),
+        _ => return Err(anyhow!("Unsupported type code {}.", r#type.code)),
+    })
+}
+
+/// Quote a string as a GoogleSQL string literal, escaping special characters.
+pub fn quote_string(s: &str) -> String {
+    let mut quoted = String::with_capacity(s.len() + 2);
+    quoted.push('\'');
+    for c in s.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '\'' => quoted.push_str("\\'"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Format a list of values as a tuple, for use when reporting and comparing keys.
+pub fn format_row(values: &[SpannerValue]) -> String {
+    format!(
+        "({})",
+        values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use google_cloud_googleapis::spanner::v1::{Type, TypeCode};
+    use prost_types::{value::Kind, ListValue};
+
+    use super::{format_row, SpannerValue};
+
+    fn value(code: TypeCode, kind: Kind) -> SpannerValue {
+        SpannerValue {
+            kind,
+            r#type: Type {
+                code: code.into(),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Test that values are formatted according to their Spanner type.
+    #[test]
+    fn test_format_row() {
+        let row = vec![
+            value(TypeCode::Int64, Kind::StringValue("42".to_string())),
+            value(
+                TypeCode::String,
+                Kind::StringValue("it's \"quoted\"".to_string()),
+            ),
+            value(TypeCode::Bool, Kind::BoolValue(true)),
+            value(TypeCode::Timestamp, Kind::NullValue(0)),
+        ];
+
+        assert_eq!(format_row(&row), r#"(42, "it's \"quoted\"", true, NULL)"#);
+    }
+
+    /// Test that values are converted to JSON according to their Spanner type.
+    #[test]
+    fn test_to_json() {
+        assert_eq!(
+            value(TypeCode::Int64, Kind::StringValue("42".to_string())).to_json(),
+            serde_json::json!(42)
+        );
+        assert_eq!(
+            value(TypeCode::Json, Kind::StringValue(r#"{"a":1}"#.to_string())).to_json(),
+            serde_json::json!({"a": 1})
+        );
+        assert_eq!(
+            value(TypeCode::Numeric, Kind::StringValue("1.50".to_string())).to_json(),
+            serde_json::json!("1.50")
+        );
+    }
+
+    /// Test that values are rendered as literals of their Spanner type.
+    #[test]
+    fn test_to_sql_literal() {
+        let cases = vec![
+            (value(TypeCode::Bool, Kind::BoolValue(true)), "TRUE"),
+            (
+                value(TypeCode::Int64, Kind::StringValue("-7".to_string())),
+                "-7",
+            ),
+            (value(TypeCode::Float64, Kind::NumberValue(1.0)), "1.0"),
+            (
+                value(TypeCode::Float64, Kind::StringValue("NaN".to_string())),
+                "CAST('nan' AS FLOAT64)",
+            ),
+            (
+                value(TypeCode::Numeric, Kind::StringValue("1.50".to_string())),
+                "NUMERIC '1.50'",
+            ),
+            (
+                value(TypeCode::String, Kind::StringValue("it's\n".to_string())),
+                r"'it\'s\n'",
+            ),
+            (
+                value(TypeCode::Bytes, Kind::StringValue("aGk=".to_string())),
+                "FROM_BASE64('aGk=')",
+            ),
+            (
+                value(TypeCode::Date, Kind::StringValue("2023-03-01".to_string())),
+                "DATE '2023-03-01'",
+            ),
+            (
+                value(
+                    TypeCode::Timestamp,
+                    Kind::StringValue("2023-03-01T23:34:43.023443Z".to_string()),
+                ),
+                "TIMESTAMP '2023-03-01T23:34:43.023443Z'",
+            ),
+            (
+                value(TypeCode::Json, Kind::StringValue(r#"{"a":1}"#.to_string())),
+                r#"JSON '{"a":1}'"#,
+            ),
+            (value(TypeCode::String, Kind::NullValue(0)), "NULL"),
+            (
+                SpannerValue {
+                    kind: Kind::ListValue(ListValue {
+                        values: vec![
+                            prost_types::Value {
+                                kind: Some(Kind::StringValue("1".to_string())),
+                            },
+                            prost_types::Value {
+                                kind: Some(Kind::NullValue(0)),
+                            },
+                        ],
+                    }),
+                    r#type: Type {
+                        code: TypeCode::Array.into(),
+                        array_element_type: Some(Box::new(Type {
+                            code: TypeCode::Int64.into(),
+                            ..Default::default()
+                        })),
+                        ..Default::default()
+                    },
+                },
+                "ARRAY<INT64>[1, NULL]",
+            ),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(value.to_sql_literal().unwrap(), expected);
+        }
+    }
+}